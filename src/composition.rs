@@ -0,0 +1,191 @@
+use std::fmt;
+
+use crate::pitch::note_from_name;
+use crate::{Generator, WaveFunction};
+
+#[derive(Debug)]
+pub enum ParseError {
+    MissingField { line: usize, field: &'static str },
+    UnknownWaveform { line: usize, name: String },
+    InvalidNumber { line: usize, value: String },
+    InvalidNote { line: usize, value: String },
+    UnexpectedField { line: usize, value: String },
+    UnknownKey { line: usize, key: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MissingField { line, field } => {
+                write!(f, "line {}: missing {}", line, field)
+            }
+            ParseError::UnknownWaveform { line, name } => {
+                write!(f, "line {}: unknown waveform {:?}", line, name)
+            }
+            ParseError::InvalidNumber { line, value } => {
+                write!(f, "line {}: invalid number {:?}", line, value)
+            }
+            ParseError::InvalidNote { line, value } => {
+                write!(f, "line {}: invalid note {:?}", line, value)
+            }
+            ParseError::UnexpectedField { line, value } => {
+                write!(f, "line {}: unexpected field {:?}", line, value)
+            }
+            ParseError::UnknownKey { line, key } => {
+                write!(
+                    f,
+                    "line {}: unknown key {:?}, expected amp or pan",
+                    line, key
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses one element per line as `waveform time duration note`, where the note is a
+/// name such as `A4` or a semitone offset from the tuning reference, optionally followed by
+/// `amp=<amplitude>` (default 1) and `pan=<-1..1>` (default 0). `rest` lines may omit the
+/// note. Blank lines and comments, which start with a field beginning with `#`, are ignored.
+pub fn parse_composition(text: &str) -> Result<Generator, ParseError> {
+    let mut generator = Generator::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let mut fields = line
+            .split_whitespace()
+            .take_while(|field| !field.starts_with('#'));
+        let name = match fields.next() {
+            Some(name) => name,
+            None => continue,
+        };
+        let function = parse_waveform(name).ok_or_else(|| ParseError::UnknownWaveform {
+            line: line_number,
+            name: name.to_string(),
+        })?;
+        let time = parse_number(fields.next(), line_number, "time")?;
+        let duration = parse_number(fields.next(), line_number, "duration")?;
+        let note = match fields.next() {
+            Some(note) => note_from_name(note)
+                .or_else(|| note.parse().ok())
+                .ok_or_else(|| ParseError::InvalidNote {
+                    line: line_number,
+                    value: note.to_string(),
+                })?,
+            None if matches!(function, WaveFunction::Rest) => 0.0,
+            None => {
+                return Err(ParseError::MissingField {
+                    line: line_number,
+                    field: "note",
+                })
+            }
+        };
+        let mut amplitude = 1.0;
+        let mut pan = 0.0;
+        for field in fields {
+            let (key, value) =
+                field
+                    .split_once('=')
+                    .ok_or_else(|| ParseError::UnexpectedField {
+                        line: line_number,
+                        value: field.to_string(),
+                    })?;
+            let target = match key {
+                "amp" => &mut amplitude,
+                "pan" => &mut pan,
+                _ => {
+                    return Err(ParseError::UnknownKey {
+                        line: line_number,
+                        key: key.to_string(),
+                    })
+                }
+            };
+            *target = parse_number(Some(value), line_number, "value")?;
+        }
+        if let WaveFunction::Rest = function {
+            generator.add_rest(time, duration);
+        } else {
+            generator
+                .add_music_element(function, time, duration, note, amplitude)
+                .pan = pan;
+        }
+    }
+    Ok(generator)
+}
+
+fn parse_waveform(name: &str) -> Option<WaveFunction> {
+    match name.to_lowercase().as_str() {
+        "square" => Some(WaveFunction::Square),
+        "sawtooth" | "saw" => Some(WaveFunction::Sawtooth),
+        "triangle" => Some(WaveFunction::Triangle),
+        "sine" => Some(WaveFunction::Sine),
+        "noise" | "whitenoise" => Some(WaveFunction::WhiteNoise),
+        "pinknoise" => Some(WaveFunction::PinkNoise),
+        "pluck" => Some(WaveFunction::Pluck),
+        "rest" => Some(WaveFunction::Rest),
+        _ => None,
+    }
+}
+
+fn parse_number(field: Option<&str>, line: usize, name: &'static str) -> Result<f32, ParseError> {
+    let field = field.ok_or(ParseError::MissingField { line, field: name })?;
+    field.parse().map_err(|_| ParseError::InvalidNumber {
+        line,
+        value: field.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_multiline_composition() {
+        let text =
+            "# intro\n\nsquare 0.0 1.0 A4\nsine 1.0 0.5 -12 amp=0.5 pan=-1 # low\nrest 1.5 1.0\n";
+        let generator = parse_composition(text).unwrap();
+        assert_eq!(generator.element_count(), 3);
+        let elements = &generator.elements;
+        assert!(matches!(elements[0].function, WaveFunction::Square));
+        assert_eq!(elements[0].duration, 1.0);
+        assert_eq!(elements[1].time, 1.0);
+        assert_eq!(elements[1].amplitude, 0.5);
+        assert_eq!(elements[1].pan, -1.0);
+        assert!(matches!(elements[2].function, WaveFunction::Rest));
+    }
+
+    #[test]
+    fn reports_malformed_line_number() {
+        let error = parse_composition("sine 0 1 A4\nsquare 0 x A4\n")
+            .err()
+            .unwrap();
+        assert!(matches!(error, ParseError::InvalidNumber { line: 2, .. }));
+        assert_eq!(error.to_string(), "line 2: invalid number \"x\"");
+        let error = parse_composition("sine 0 1").err().unwrap();
+        assert!(matches!(
+            error,
+            ParseError::MissingField {
+                line: 1,
+                field: "note"
+            }
+        ));
+    }
+
+    #[test]
+    fn optional_amp_and_pan_fields() {
+        let generator = parse_composition("square 0 1 A4\nsaw 1 1 C4 pan=0.5 amp=0.8\n").unwrap();
+        let elements = &generator.elements;
+        assert_eq!((elements[0].amplitude, elements[0].pan), (1.0, 0.0));
+        assert_eq!((elements[1].amplitude, elements[1].pan), (0.8, 0.5));
+        let error = parse_composition("sine 0 1 A4\nsine 0 1 A4 gain=2\n")
+            .err()
+            .unwrap();
+        assert!(matches!(error, ParseError::UnknownKey { line: 2, .. }));
+        assert_eq!(
+            error.to_string(),
+            "line 2: unknown key \"gain\", expected amp or pan"
+        );
+        let error = parse_composition("sine 0 1 A4 loud").err().unwrap();
+        assert!(matches!(error, ParseError::UnexpectedField { line: 1, .. }));
+    }
+}
@@ -0,0 +1,1288 @@
+use crate::error::MAX_RENDER_SECONDS;
+use crate::oscillator::{sample_at, sine, Interpolation};
+
+pub fn normalize(wave: &mut [f32], target_peak: f32) {
+    let peak = wave
+        .iter()
+        .fold(0f32, |peak, sample| peak.max(sample.abs()));
+    if peak == 0.0 {
+        return;
+    }
+    let gain = target_peak / peak;
+    for sample in wave.iter_mut() {
+        *sample *= gain;
+    }
+}
+
+pub fn low_pass(wave: &mut [f32], cutoff_hz: f32, sample_rate: f32) {
+    let cutoff_hz = cutoff_hz.max(0.0).min(sample_rate / 2.0);
+    let alpha = 1.0 - (-2.0 * std::f32::consts::PI * cutoff_hz / sample_rate).exp();
+    let mut previous = 0f32;
+    for sample in wave.iter_mut() {
+        previous += alpha * (*sample - previous);
+        *sample = previous;
+    }
+}
+
+/// Extends the buffer so the echoes can ring out until they fall below -60 dB.
+pub fn delay(wave: &mut Vec<f32>, delay_secs: f32, feedback: f32, mix: f32, sample_rate: f32) {
+    let delay_samples = (delay_secs * sample_rate) as usize;
+    if delay_samples == 0 || wave.is_empty() {
+        return;
+    }
+    let feedback = feedback.clamp(0.0, 0.99);
+    let echoes = if feedback > 0.0 {
+        1 + (0.001f32.ln() / feedback.ln()).ceil() as usize
+    } else {
+        1
+    };
+    let dry_length = wave.len();
+    wave.resize(dry_length + delay_samples * echoes, 0.0);
+    let mut delayed = vec![0f32; wave.len()];
+    for sample in delay_samples..wave.len() {
+        let source = sample - delay_samples;
+        let dry = if source < dry_length {
+            wave[source]
+        } else {
+            0.0
+        };
+        delayed[sample] = dry + feedback * delayed[source];
+    }
+    for (sample, delayed) in wave.iter_mut().zip(delayed.iter()) {
+        *sample += mix * delayed;
+    }
+}
+
+/// Echoes alternate sides: left input is heard first on the right, then back on the left.
+/// Each bounce is scaled by `feedback`, so the first echo already has that level.
+pub fn ping_pong_delay(
+    left: &mut Vec<f32>,
+    right: &mut Vec<f32>,
+    delay_secs: f32,
+    feedback: f32,
+    sample_rate: f32,
+) {
+    let delay_samples = (delay_secs * sample_rate) as usize;
+    let feedback = feedback.clamp(0.0, 0.99);
+    if delay_samples == 0 || feedback == 0.0 {
+        return;
+    }
+    let echoes = 1 + (0.001f32.ln() / feedback.ln()).ceil() as usize;
+    let dry_length = left.len().max(right.len());
+    let length = dry_length + delay_samples * echoes;
+    left.resize(length, 0.0);
+    right.resize(length, 0.0);
+    let mut echo_left = vec![0f32; length];
+    let mut echo_right = vec![0f32; length];
+    for sample in delay_samples..length {
+        let source = sample - delay_samples;
+        echo_left[sample] = feedback * (right[source] + echo_right[source]);
+        echo_right[sample] = feedback * (left[source] + echo_left[source]);
+    }
+    for (sample, echo) in left.iter_mut().zip(echo_left.iter()) {
+        *sample += echo;
+    }
+    for (sample, echo) in right.iter_mut().zip(echo_right.iter()) {
+        *sample += echo;
+    }
+}
+
+pub fn fade_in(wave: &mut [f32], secs: f32, sample_rate: f32) {
+    let fade_samples = ((secs * sample_rate) as usize).min(wave.len());
+    for (index, sample) in wave[..fade_samples].iter_mut().enumerate() {
+        *sample *= index as f32 / fade_samples as f32;
+    }
+}
+
+pub fn fade_out(wave: &mut [f32], secs: f32, sample_rate: f32) {
+    let fade_samples = ((secs * sample_rate) as usize).min(wave.len());
+    let start = wave.len() - fade_samples;
+    for (index, sample) in wave[start..].iter_mut().enumerate() {
+        *sample *= 1.0 - (index + 1) as f32 / fade_samples as f32;
+    }
+}
+
+pub fn limit(wave: &mut [f32], threshold: f32) {
+    let threshold = threshold.abs();
+    for sample in wave.iter_mut() {
+        if sample.abs() > threshold {
+            *sample = threshold.copysign(*sample);
+        }
+    }
+}
+
+/// Samples within ±0.5 pass unchanged; above that a tanh knee, matched in slope at 0.5,
+/// bends them towards ±1 so 2.0 comes out just under full scale instead of squared off.
+pub fn soft_clip(wave: &mut [f32]) {
+    const KNEE: f32 = 0.5;
+    for sample in wave.iter_mut() {
+        let level = sample.abs();
+        if level > KNEE {
+            let bent = KNEE + (1.0 - KNEE) * ((level - KNEE) / (1.0 - KNEE)).tanh();
+            *sample = bent.copysign(*sample);
+        }
+    }
+}
+
+pub fn reverse(wave: &mut [f32]) {
+    wave.reverse();
+}
+
+pub fn distort(wave: &mut [f32], drive: f32) {
+    if drive <= 0.0 {
+        return;
+    }
+    let normalization = drive.tanh();
+    for sample in wave.iter_mut() {
+        *sample = ((drive * *sample).tanh() / normalization).clamp(-1.0, 1.0);
+    }
+}
+
+/// `bits` is clamped to 1..=16 and a `downsample_factor` of 0 is treated as 1.
+pub fn bitcrush(wave: &mut [f32], bits: u32, downsample_factor: u32) {
+    let levels = ((1u32 << bits.clamp(1, 16)) - 1) as f32;
+    let downsample_factor = downsample_factor.max(1) as usize;
+    let mut held = 0f32;
+    for (index, sample) in wave.iter_mut().enumerate() {
+        if index % downsample_factor == 0 {
+            let position = (sample.clamp(-1.0, 1.0) + 1.0) / 2.0;
+            held = (position * levels).round() / levels * 2.0 - 1.0;
+        }
+        *sample = held;
+    }
+}
+
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    fn new(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Biquad {
+        Biquad {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+    fn process(&self, wave: &mut [f32]) {
+        let (mut x1, mut x2, mut y1, mut y2) = (0f32, 0f32, 0f32, 0f32);
+        for sample in wave.iter_mut() {
+            let x0 = *sample;
+            let y0 = self.b0 * x0 + self.b1 * x1 + self.b2 * x2 - self.a1 * y1 - self.a2 * y2;
+            x2 = x1;
+            x1 = x0;
+            y2 = y1;
+            y1 = y0;
+            *sample = y0;
+        }
+    }
+}
+
+fn biquad_angle(frequency: f32, sample_rate: f32) -> f32 {
+    let frequency = frequency.max(1.0).min(sample_rate * 0.49);
+    2.0 * std::f32::consts::PI * frequency / sample_rate
+}
+
+pub fn high_pass(wave: &mut [f32], cutoff_hz: f32, sample_rate: f32) {
+    let w0 = biquad_angle(cutoff_hz, sample_rate);
+    let alpha = w0.sin() * std::f32::consts::FRAC_1_SQRT_2;
+    let cos = w0.cos();
+    Biquad::new(
+        (1.0 + cos) / 2.0,
+        -(1.0 + cos),
+        (1.0 + cos) / 2.0,
+        1.0 + alpha,
+        -2.0 * cos,
+        1.0 - alpha,
+    )
+    .process(wave);
+}
+
+pub fn band_pass(wave: &mut [f32], center_hz: f32, q: f32, sample_rate: f32) {
+    let q = if q > 0.0 {
+        q
+    } else {
+        std::f32::consts::FRAC_1_SQRT_2
+    };
+    let w0 = biquad_angle(center_hz, sample_rate);
+    let alpha = w0.sin() / (2.0 * q);
+    let cos = w0.cos();
+    Biquad::new(alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha).process(wave);
+}
+
+pub fn remove_dc(wave: &mut [f32]) {
+    if wave.is_empty() {
+        return;
+    }
+    let mean = wave.iter().sum::<f32>() / wave.len() as f32;
+    for sample in wave.iter_mut() {
+        *sample -= mean;
+    }
+}
+
+pub fn crossfade(a: &[f32], b: &[f32], overlap: usize) -> Vec<f32> {
+    let overlap = overlap.min(a.len()).min(b.len());
+    let start = a.len() - overlap;
+    let mut wave = Vec::with_capacity(a.len() + b.len() - overlap);
+    wave.extend_from_slice(&a[..start]);
+    for index in 0..overlap {
+        let progress = (index as f32 + 0.5) / overlap as f32;
+        let angle = progress * std::f32::consts::FRAC_PI_2;
+        wave.push(a[start + index] * angle.cos() + b[index] * angle.sin());
+    }
+    wave.extend_from_slice(&b[overlap..]);
+    wave
+}
+
+/// Delays the right channel; both buffers grow by the delay so nothing is cut off.
+pub fn widen(left: &mut Vec<f32>, right: &mut Vec<f32>, delay_ms: f32, sample_rate: f32) {
+    let delay_samples = (delay_ms.max(0.0) * sample_rate / 1000.0) as usize;
+    if delay_samples == 0 {
+        return;
+    }
+    let length = left.len().max(right.len()) + delay_samples;
+    left.resize(length, 0.0);
+    right.splice(0..0, std::iter::repeat_n(0.0, delay_samples));
+    right.resize(length, 0.0);
+}
+
+struct Comb {
+    buffer: Vec<f32>,
+    index: usize,
+    filtered: f32,
+}
+
+impl Comb {
+    fn new(length: usize) -> Comb {
+        Comb {
+            buffer: vec![0.0; length.max(1)],
+            index: 0,
+            filtered: 0.0,
+        }
+    }
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.index];
+        self.filtered = output * (1.0 - damping) + self.filtered * damping;
+        self.buffer[self.index] = input + self.filtered * feedback;
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+    fn new(length: usize) -> Allpass {
+        Allpass {
+            buffer: vec![0.0; length.max(1)],
+            index: 0,
+        }
+    }
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.index];
+        self.buffer[self.index] = input + delayed * 0.5;
+        self.index = (self.index + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+
+/// Offset added to every right-channel delay line so the two tails decorrelate.
+const STEREO_SPREAD: usize = 23;
+
+struct ReverbTank {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl ReverbTank {
+    fn new(scale: f32, spread: usize) -> ReverbTank {
+        ReverbTank {
+            combs: COMB_TUNING
+                .iter()
+                .map(|&length| Comb::new(((length + spread) as f32 * scale) as usize))
+                .collect(),
+            allpasses: ALLPASS_TUNING
+                .iter()
+                .map(|&length| Allpass::new(((length + spread) as f32 * scale) as usize))
+                .collect(),
+        }
+    }
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let mut wet = self
+            .combs
+            .iter_mut()
+            .map(|comb| comb.process(input, feedback, damping))
+            .sum::<f32>();
+        for allpass in self.allpasses.iter_mut() {
+            wet = allpass.process(wet);
+        }
+        wet
+    }
+}
+
+fn reverb_tail(room_size: f32, sample_rate: f32, spread: usize) -> (f32, usize) {
+    let feedback = 0.7 + 0.28 * room_size.clamp(0.0, 1.0);
+    let longest_comb = ((COMB_TUNING[7] + spread) as f32 * sample_rate / 44100.0) as usize;
+    let tail = longest_comb * (1 + (0.001f32.ln() / feedback.ln()).ceil() as usize);
+    (feedback, tail)
+}
+
+/// Freeverb-style network; delay lengths are tuned for 44.1 kHz and scaled to `sample_rate`.
+/// `room_size`, `damping` and `mix` are clamped to 0..1 and the buffer grows by the tail.
+pub fn reverb(wave: &mut Vec<f32>, room_size: f32, damping: f32, mix: f32, sample_rate: f32) {
+    if wave.is_empty() {
+        return;
+    }
+    let (feedback, tail) = reverb_tail(room_size, sample_rate, 0);
+    let damping = 0.4 * damping.clamp(0.0, 1.0);
+    let mix = mix.clamp(0.0, 1.0);
+    let mut tank = ReverbTank::new(sample_rate / 44100.0, 0);
+    let dry_length = wave.len();
+    wave.resize(dry_length + tail, 0.0);
+    for sample in wave.iter_mut() {
+        let wet = tank.process(*sample * 0.045, feedback, damping);
+        *sample = *sample * (1.0 - mix) + wet * mix;
+    }
+}
+
+/// Both channels feed two tanks with offset delay lines; `width` 0 blends their outputs
+/// into the same mono tail and 1 keeps them fully separate.
+#[allow(clippy::too_many_arguments)]
+pub fn reverb_stereo(
+    left: &mut Vec<f32>,
+    right: &mut Vec<f32>,
+    room_size: f32,
+    damping: f32,
+    width: f32,
+    mix: f32,
+    sample_rate: f32,
+) {
+    if left.is_empty() && right.is_empty() {
+        return;
+    }
+    let (feedback, tail) = reverb_tail(room_size, sample_rate, STEREO_SPREAD);
+    let damping = 0.4 * damping.clamp(0.0, 1.0);
+    let mix = mix.clamp(0.0, 1.0);
+    let width = width.clamp(0.0, 1.0);
+    let direct = 0.5 + 0.5 * width;
+    let cross = 0.5 - 0.5 * width;
+    let scale = sample_rate / 44100.0;
+    let mut left_tank = ReverbTank::new(scale, 0);
+    let mut right_tank = ReverbTank::new(scale, STEREO_SPREAD);
+    let length = left.len().max(right.len()) + tail;
+    left.resize(length, 0.0);
+    right.resize(length, 0.0);
+    for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+        let input = (*left + *right) * 0.5 * 0.045;
+        let left_wet = left_tank.process(input, feedback, damping);
+        let right_wet = right_tank.process(input, feedback, damping);
+        *left = *left * (1.0 - mix) + (left_wet * direct + right_wet * cross) * mix;
+        *right = *right * (1.0 - mix) + (right_wet * direct + left_wet * cross) * mix;
+    }
+}
+
+/// One-pole peak follower; `attack_ms` and `release_ms` are the time constants for rising
+/// and falling levels, and 0 follows instantly.
+pub fn envelope_follower(
+    wave: &[f32],
+    attack_ms: f32,
+    release_ms: f32,
+    sample_rate: f32,
+) -> Vec<f32> {
+    let coefficient = |ms: f32| {
+        if ms > 0.0 {
+            (-1000.0 / (ms * sample_rate)).exp()
+        } else {
+            0.0
+        }
+    };
+    let attack = coefficient(attack_ms);
+    let release = coefficient(release_ms);
+    let mut envelope = 0f32;
+    wave.iter()
+        .map(|sample| {
+            let level = sample.abs();
+            let smoothing = if level > envelope { attack } else { release };
+            envelope = level + smoothing * (envelope - level);
+            envelope
+        })
+        .collect()
+}
+
+pub fn compress(
+    wave: &mut [f32],
+    threshold: f32,
+    ratio: f32,
+    attack_ms: f32,
+    release_ms: f32,
+    sample_rate: f32,
+) {
+    let threshold = threshold.abs();
+    let ratio = ratio.max(1.0);
+    if ratio == 1.0 || threshold == 0.0 {
+        return;
+    }
+    let envelope = envelope_follower(wave, attack_ms, release_ms, sample_rate);
+    for (sample, envelope) in wave.iter_mut().zip(envelope) {
+        if envelope > threshold {
+            let compressed = threshold * (envelope / threshold).powf(1.0 / ratio);
+            *sample *= compressed / envelope;
+        }
+    }
+}
+
+pub fn detect_clipping(wave: &[f32], threshold: f32) -> Vec<usize> {
+    let threshold = threshold.abs();
+    wave.iter()
+        .enumerate()
+        .filter(|(_, sample)| sample.abs() > threshold)
+        .map(|(index, _)| index)
+        .collect()
+}
+
+pub fn rms(wave: &[f32]) -> f32 {
+    if wave.is_empty() {
+        return 0.0;
+    }
+    (wave.iter().map(|sample| sample * sample).sum::<f32>() / wave.len() as f32).sqrt()
+}
+
+#[derive(Clone, Copy)]
+pub struct AudioStats {
+    pub peak: f32,
+    pub rms: f32,
+    pub dc_offset: f32,
+    pub clip_count: usize,
+}
+
+/// Samples beyond full scale (magnitude above 1) count as clipped.
+pub fn analyze(wave: &[f32]) -> AudioStats {
+    let mut peak = 0f32;
+    let mut sum = 0.0;
+    let mut sum_squares = 0.0;
+    let mut clip_count = 0;
+    for &sample in wave {
+        peak = peak.max(sample.abs());
+        sum += sample;
+        sum_squares += sample * sample;
+        if sample.abs() > 1.0 {
+            clip_count += 1;
+        }
+    }
+    let length = wave.len().max(1) as f32;
+    AudioStats {
+        peak,
+        rms: (sum_squares / length).sqrt(),
+        dc_offset: sum / length,
+        clip_count,
+    }
+}
+
+/// Hann-windowed FFT of `window_size` samples from `start`, zero-padded to a power of two.
+/// Magnitudes are scaled so a full-scale sine reads close to 1 in its bin.
+pub fn spectrum(
+    wave: &[f32],
+    start: usize,
+    window_size: usize,
+    sample_rate: f32,
+) -> Vec<(f32, f32)> {
+    if window_size == 0 {
+        return Vec::new();
+    }
+    let size = window_size.next_power_of_two();
+    let mut real = vec![0f32; size];
+    let mut imaginary = vec![0f32; size];
+    let mut window_sum = 0.0;
+    for (index, value) in real.iter_mut().take(window_size).enumerate() {
+        let window =
+            0.5 - 0.5 * (2.0 * std::f32::consts::PI * index as f32 / window_size as f32).cos();
+        window_sum += window;
+        *value = wave.get(start + index).copied().unwrap_or(0.0) * window;
+    }
+    fft(&mut real, &mut imaginary);
+    (0..=size / 2)
+        .map(|bin| {
+            let magnitude = real[bin].hypot(imaginary[bin]) * 2.0 / window_sum;
+            (bin as f32 * sample_rate / size as f32, magnitude)
+        })
+        .collect()
+}
+
+/// Overlap-add FFT convolution; the buffer grows by `impulse.len() - 1` samples for the tail.
+/// Impulse responses can be loaded with `WaveFunction::from_wav`.
+pub fn convolve(wave: &mut Vec<f32>, impulse: &[f32]) {
+    if impulse.is_empty() || wave.is_empty() {
+        return;
+    }
+    let block_size = impulse.len().next_power_of_two();
+    let size = block_size * 2;
+    let mut impulse_real = vec![0f32; size];
+    let mut impulse_imaginary = vec![0f32; size];
+    impulse_real[..impulse.len()].copy_from_slice(impulse);
+    fft(&mut impulse_real, &mut impulse_imaginary);
+    let mut output = vec![0f32; wave.len() + impulse.len() - 1];
+    for (block, input) in wave.chunks(block_size).enumerate() {
+        let mut real = vec![0f32; size];
+        let mut imaginary = vec![0f32; size];
+        real[..input.len()].copy_from_slice(input);
+        fft(&mut real, &mut imaginary);
+        // Conjugating the product lets the forward transform compute the inverse.
+        for bin in 0..size {
+            let product_real =
+                real[bin] * impulse_real[bin] - imaginary[bin] * impulse_imaginary[bin];
+            let product_imaginary =
+                real[bin] * impulse_imaginary[bin] + imaginary[bin] * impulse_real[bin];
+            real[bin] = product_real;
+            imaginary[bin] = -product_imaginary;
+        }
+        fft(&mut real, &mut imaginary);
+        let offset = block * block_size;
+        for (sample, value) in output[offset..].iter_mut().zip(real.iter()) {
+            *sample += value / size as f32;
+        }
+    }
+    *wave = output;
+}
+
+/// In-place iterative radix-2 Cooley-Tukey; the length must be a power of two.
+fn fft(real: &mut [f32], imaginary: &mut [f32]) {
+    let size = real.len();
+    let mut target = 0;
+    for index in 1..size {
+        let mut bit = size >> 1;
+        while target & bit != 0 {
+            target ^= bit;
+            bit >>= 1;
+        }
+        target |= bit;
+        if index < target {
+            real.swap(index, target);
+            imaginary.swap(index, target);
+        }
+    }
+    let mut length = 2;
+    while length <= size {
+        let angle = -2.0 * std::f32::consts::PI / length as f32;
+        for block in (0..size).step_by(length) {
+            for offset in 0..length / 2 {
+                let (sin, cos) = (angle * offset as f32).sin_cos();
+                let even = block + offset;
+                let odd = even + length / 2;
+                let odd_real = real[odd] * cos - imaginary[odd] * sin;
+                let odd_imaginary = real[odd] * sin + imaginary[odd] * cos;
+                real[odd] = real[even] - odd_real;
+                imaginary[odd] = imaginary[even] - odd_imaginary;
+                real[even] += odd_real;
+                imaginary[even] += odd_imaginary;
+            }
+        }
+        length <<= 1;
+    }
+}
+
+pub fn normalize_rms(wave: &mut [f32], target_rms: f32) {
+    let current = rms(wave);
+    if current == 0.0 {
+        return;
+    }
+    let gain = target_rms / current;
+    for sample in wave.iter_mut() {
+        *sample *= gain;
+    }
+}
+
+/// Points are `(time, value)` sorted by time; the ends hold their values and no points give 0.
+pub fn interpolate_points(points: &[(f32, f32)], time: f32) -> f32 {
+    let after = points.partition_point(|&(point_time, _)| point_time <= time);
+    match (
+        after.checked_sub(1).map(|before| points[before]),
+        points.get(after),
+    ) {
+        (None, None) => 0.0,
+        (Some((_, value)), None) | (None, Some(&(_, value))) => value,
+        (Some((start_time, start)), Some(&(end_time, end))) => {
+            start + (end - start) * (time - start_time) / (end_time - start_time)
+        }
+    }
+}
+
+pub fn apply_gain_curve(wave: &mut [f32], points: &[(f32, f32)], sample_rate: f32) {
+    if points.is_empty() {
+        return;
+    }
+    for (index, sample) in wave.iter_mut().enumerate() {
+        *sample *= interpolate_points(points, index as f32 / sample_rate);
+    }
+}
+
+pub fn trim_silence(wave: &mut Vec<f32>, threshold: f32) {
+    let threshold = threshold.abs();
+    let audible = |sample: &f32| sample.abs() >= threshold;
+    let end = match wave.iter().rposition(audible) {
+        Some(last) => last + 1,
+        None => {
+            wave.clear();
+            return;
+        }
+    };
+    let start = wave.iter().position(audible).unwrap_or(0);
+    wave.truncate(end);
+    wave.drain(..start);
+}
+
+/// Overwrites the `repeats` slices following the captured one, stopping at the end of the buffer.
+pub fn stutter(wave: &mut [f32], start: f32, slice_len: f32, repeats: u32, sample_rate: f32) {
+    let start = (start.max(0.0) * sample_rate) as usize;
+    let slice_samples = (slice_len.max(0.0) * sample_rate) as usize;
+    if slice_samples == 0 || start + slice_samples > wave.len() {
+        return;
+    }
+    for repeat in 1..=repeats as usize {
+        let offset = start + repeat * slice_samples;
+        if offset >= wave.len() {
+            break;
+        }
+        let count = slice_samples.min(wave.len() - offset);
+        wave.copy_within(start..start + count, offset);
+    }
+}
+
+/// Each pulse ramps in and out over up to 2 ms inside its on portion to avoid clicks.
+pub fn gate(wave: &mut [f32], rate_hz: f32, duty: f32, sample_rate: f32) {
+    if rate_hz <= 0.0 {
+        return;
+    }
+    let period = sample_rate / rate_hz;
+    let on_samples = period * duty.clamp(0.0, 1.0);
+    let ramp = (0.002 * sample_rate).min(on_samples / 2.0);
+    for (index, sample) in wave.iter_mut().enumerate() {
+        let position = index as f32 % period;
+        let gain = if position >= on_samples {
+            0.0
+        } else if ramp > 0.0 {
+            (position / ramp)
+                .min((on_samples - position) / ramp)
+                .min(1.0)
+        } else {
+            1.0
+        };
+        *sample *= gain;
+    }
+}
+
+/// Resamples with linear interpolation, so the length changes along with the pitch.
+/// `semitones` is clamped to +/-120 and the output to `MAX_RENDER_SECONDS` at `sample_rate`.
+pub fn pitch_shift(wave: &[f32], semitones: f32, sample_rate: f32) -> Vec<f32> {
+    let ratio = 2.0f32.powf(semitones.clamp(-120.0, 120.0) / 12.0);
+    let max_length = (MAX_RENDER_SECONDS * sample_rate.max(0.0)) as usize;
+    let length = ((wave.len() as f32 / ratio) as usize).min(max_length);
+    (0..length)
+        .map(|index| sample_at(wave, index as f32 * ratio))
+        .collect()
+}
+
+/// Cubic (Catmull-Rom) interpolation. When downsampling, the input first goes through two
+/// Butterworth low-pass stages at 45% of the new rate to keep aliasing down; content right
+/// at the new Nyquist frequency is attenuated rather than perfectly preserved.
+pub fn resample(wave: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == 0 || to_rate == 0 || from_rate == to_rate {
+        return wave.to_vec();
+    }
+    let mut source = wave.to_vec();
+    if to_rate < from_rate {
+        let w0 = biquad_angle(0.45 * to_rate as f32, from_rate as f32);
+        let alpha = w0.sin() * std::f32::consts::FRAC_1_SQRT_2;
+        let cos = w0.cos();
+        let anti_alias = Biquad::new(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        );
+        anti_alias.process(&mut source);
+        anti_alias.process(&mut source);
+    }
+    let ratio = from_rate as f64 / to_rate as f64;
+    let length = (wave.len() as f64 / ratio).round() as usize;
+    (0..length)
+        .map(|index| Interpolation::Cubic.sample(&source, (index as f64 * ratio) as f32))
+        .collect()
+}
+
+/// WSOLA: 40 ms Hann frames at 50% overlap, each shifted by up to a fifth of a hop so it lines
+/// up with the waveform that would naturally have followed the previous frame.
+pub fn time_stretch(wave: &[f32], factor: f32, sample_rate: f32) -> Vec<f32> {
+    if factor <= 0.0 || wave.is_empty() {
+        return wave.to_vec();
+    }
+    let frame = ((0.04 * sample_rate) as usize).max(16);
+    let hop = frame / 2;
+    let tolerance = hop / 5;
+    let window: Vec<f32> = (0..frame)
+        .map(|index| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * index as f32 / frame as f32).cos())
+        .collect();
+    let input = |position: usize| wave.get(position).copied().unwrap_or(0.0);
+    let length = (wave.len() as f32 * factor) as usize;
+    let mut stretched = vec![0f32; length + frame];
+    let mut previous: Option<usize> = None;
+    let mut output_position = 0;
+    while output_position < length {
+        let nominal = (output_position as f32 / factor) as usize;
+        let position = match previous {
+            None => nominal,
+            Some(previous) => {
+                let natural = previous + hop;
+                let lowest = nominal.saturating_sub(tolerance);
+                (lowest..=nominal + tolerance)
+                    .map(|candidate| {
+                        let similarity: f32 = (0..hop)
+                            .map(|offset| input(natural + offset) * input(candidate + offset))
+                            .sum();
+                        (candidate, similarity)
+                    })
+                    .fold((nominal, f32::MIN), |best, candidate| {
+                        if candidate.1 > best.1 {
+                            candidate
+                        } else {
+                            best
+                        }
+                    })
+                    .0
+            }
+        };
+        for (offset, gain) in window.iter().enumerate() {
+            stretched[output_position + offset] += gain * input(position + offset);
+        }
+        previous = Some(position);
+        output_position += hop;
+    }
+    stretched.truncate(length);
+    stretched
+}
+
+/// The delay line sweeps between 7 ms and 7 ms plus `depth_ms`.
+pub fn chorus(wave: &mut [f32], rate_hz: f32, depth_ms: f32, mix: f32, sample_rate: f32) {
+    let mix = mix.clamp(0.0, 1.0);
+    let base = 0.007 * sample_rate;
+    let depth = depth_ms.max(0.0) * sample_rate / 1000.0;
+    let mut line = vec![0f32; (base + depth).ceil() as usize + 2];
+    let mut write = 0;
+    for (index, sample) in wave.iter_mut().enumerate() {
+        line[write] = *sample;
+        let lfo = 0.5 + 0.5 * sine(rate_hz * index as f32 / sample_rate);
+        let delay = base + depth * lfo;
+        let read = (write as f32 - delay).rem_euclid(line.len() as f32);
+        let wet = sample_at_wrapped(&line, read);
+        *sample = *sample * (1.0 - mix) + wet * mix;
+        write = (write + 1) % line.len();
+    }
+}
+
+fn sample_at_wrapped(line: &[f32], position: f32) -> f32 {
+    let index = position.floor() as usize % line.len();
+    let next = (index + 1) % line.len();
+    let fraction = position - position.floor();
+    line[index] + (line[next] - line[index]) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reverb_stereo_width_decorrelates_tails() {
+        let impulse = |length| {
+            let mut wave = vec![0.0; length];
+            wave[0] = 1.0;
+            wave
+        };
+        let (mut left, mut right) = (impulse(100), impulse(100));
+        reverb_stereo(&mut left, &mut right, 0.5, 0.5, 1.0, 0.5, 8000.0);
+        assert_ne!(left[100..], right[100..]);
+        let (mut left, mut right) = (impulse(100), impulse(100));
+        reverb_stereo(&mut left, &mut right, 0.5, 0.5, 0.0, 0.5, 8000.0);
+        assert_eq!(left, right);
+        let (mut left, mut right) = (impulse(100), impulse(100));
+        reverb_stereo(&mut left, &mut right, 0.5, 0.5, 1.0, 0.0, 8000.0);
+        assert_eq!(left[..100], impulse(100)[..]);
+        assert_eq!(right[..100], impulse(100)[..]);
+    }
+
+    fn sine_wave(frequency: f32, sample_rate: f32, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|index| sine(frequency * index as f32 / sample_rate))
+            .collect()
+    }
+
+    #[test]
+    fn low_pass_attenuates_high_frequencies_more() {
+        let mut low = sine_wave(200.0, 48000.0, 4800);
+        let mut high = sine_wave(8000.0, 48000.0, 4800);
+        low_pass(&mut low, 1000.0, 48000.0);
+        low_pass(&mut high, 1000.0, 48000.0);
+        assert!(rms(&high[480..]) < rms(&low[480..]) * 0.5);
+        let mut unclamped = sine_wave(8000.0, 48000.0, 4800);
+        low_pass(&mut unclamped, 1e9, 48000.0);
+        assert!(unclamped.iter().all(|sample| sample.is_finite()));
+    }
+
+    #[test]
+    fn delay_echoes_click_at_decreasing_levels() {
+        let mut wave = vec![0.0; 100];
+        wave[0] = 1.0;
+        delay(&mut wave, 0.01, 0.5, 1.0, 1000.0);
+        assert_eq!(wave.len(), 100 + 10 * 11);
+        assert_eq!(wave[0], 1.0);
+        let echoes: Vec<f32> = (1..5).map(|echo| wave[echo * 10]).collect();
+        assert_eq!(echoes, vec![1.0, 0.5, 0.25, 0.125]);
+        assert!(wave
+            .iter()
+            .enumerate()
+            .all(|(index, sample)| index % 10 == 0 || *sample == 0.0));
+        let mut runaway = vec![1.0];
+        delay(&mut runaway, 0.01, 5.0, 1.0, 1000.0);
+        assert!(runaway.iter().all(|sample| sample.abs() <= 1.0));
+    }
+
+    #[test]
+    fn fades_ramp_buffer_edges() {
+        let mut wave = vec![1.0; 1000];
+        fade_in(&mut wave, 0.1, 1000.0);
+        assert_eq!(wave[0], 0.0);
+        assert!((wave[50] - 0.5).abs() < 1e-6);
+        assert_eq!(wave[100], 1.0);
+        fade_out(&mut wave, 0.1, 1000.0);
+        assert_eq!(wave[899], 1.0);
+        assert_eq!(wave[999], 0.0);
+        let mut short = vec![1.0; 10];
+        fade_in(&mut short, 1.0, 1000.0);
+        assert_eq!(short[0], 0.0);
+        assert!(short[9] < 1.0);
+    }
+
+    #[test]
+    fn limit_only_touches_peaks() {
+        let mut wave = vec![1.5, 0.2, -1.5, -0.2];
+        limit(&mut wave, 0.8);
+        assert!(wave[0] <= 0.8 && wave[2] >= -0.8);
+        assert_eq!((wave[1], wave[3]), (0.2, -0.2));
+    }
+
+    #[test]
+    fn reversing_twice_restores_buffer() {
+        let original: Vec<f32> = (0..10).map(|index| index as f32 * 0.1).collect();
+        let mut wave = original.clone();
+        reverse(&mut wave);
+        assert_eq!(wave[0], original[9]);
+        reverse(&mut wave);
+        assert_eq!(wave, original);
+    }
+
+    #[test]
+    fn distortion_adds_bounded_harmonics() {
+        let harmonic_energy = |wave: &[f32]| -> f32 {
+            spectrum(wave, 0, 8192, 48000.0)
+                .into_iter()
+                .filter(|(frequency, _)| *frequency > 600.0)
+                .map(|(_, magnitude)| magnitude * magnitude)
+                .sum()
+        };
+        let clean = sine_wave(440.0, 48000.0, 8192);
+        let mut driven = clean.clone();
+        distort(&mut driven, 5.0);
+        assert!(harmonic_energy(&driven) > 100.0 * harmonic_energy(&clean));
+        let mut loud: Vec<f32> = clean.iter().map(|sample| sample * 10.0).collect();
+        distort(&mut loud, 50.0);
+        assert!(loud.iter().all(|sample| sample.abs() <= 1.0));
+    }
+
+    #[test]
+    fn two_bit_crush_leaves_few_levels() {
+        let mut wave = sine_wave(100.0, 8000.0, 800);
+        bitcrush(&mut wave, 2, 1);
+        let mut levels: Vec<f32> = Vec::new();
+        for sample in wave.iter() {
+            if !levels.contains(sample) {
+                levels.push(*sample);
+            }
+        }
+        assert!(levels.len() <= 4, "{:?}", levels);
+        let mut held = sine_wave(100.0, 8000.0, 800);
+        bitcrush(&mut held, 16, 4);
+        assert!(held
+            .chunks(4)
+            .all(|chunk| chunk.iter().all(|&sample| sample == chunk[0])));
+    }
+
+    #[test]
+    fn high_and_band_pass_shape_spectrum() {
+        let filtered_rms = |frequency: f32, filter: &dyn Fn(&mut [f32])| {
+            let mut wave = sine_wave(frequency, 48000.0, 9600);
+            filter(&mut wave);
+            rms(&wave[4800..])
+        };
+        let high = |wave: &mut [f32]| high_pass(wave, 2000.0, 48000.0);
+        assert!(filtered_rms(100.0, &high) < 0.1 * filtered_rms(8000.0, &high));
+        let band = |wave: &mut [f32]| band_pass(wave, 1000.0, 2.0, 48000.0);
+        let center = filtered_rms(1000.0, &band);
+        assert!((center - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.02);
+        assert!(filtered_rms(100.0, &band) < 0.1 * center);
+        assert!(filtered_rms(10000.0, &band) < 0.1 * center);
+        let guarded = |wave: &mut [f32]| band_pass(wave, 1e6, -1.0, 48000.0);
+        assert!(filtered_rms(1000.0, &guarded).is_finite());
+    }
+
+    #[test]
+    fn remove_dc_centres_offset_buffer() {
+        let mut wave: Vec<f32> = sine_wave(50.0, 8000.0, 800)
+            .into_iter()
+            .map(|sample| sample + 0.3)
+            .collect();
+        remove_dc(&mut wave);
+        let mean = wave.iter().sum::<f32>() / wave.len() as f32;
+        assert!(mean.abs() < 1e-5);
+        remove_dc(&mut []);
+    }
+
+    #[test]
+    fn crossfade_keeps_power_constant() {
+        let a = vec![0.5; 200];
+        let b = vec![-0.5; 300];
+        let faded = crossfade(&a, &b, 100);
+        assert_eq!(faded.len(), 400);
+        assert_eq!(faded[..100], a[..100]);
+        assert_eq!(faded[200..], b[100..]);
+        let fade_out = crossfade(&[1.0; 200], &[0.0; 300], 100);
+        let fade_in = crossfade(&[0.0; 200], &[1.0; 300], 100);
+        for index in 100..200 {
+            let power = fade_out[index].powi(2) + fade_in[index].powi(2);
+            assert!((power - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn widen_offsets_right_channel() {
+        let mut left = sine_wave(440.0, 1000.0, 100);
+        let mut right = left.clone();
+        widen(&mut left, &mut right, 10.0, 1000.0);
+        assert_eq!(left.len(), 110);
+        assert_eq!(right.len(), 110);
+        assert!(right[..10].iter().all(|&sample| sample == 0.0));
+        assert_eq!(&right[10..], &left[..100]);
+        let mut mono = left.clone();
+        let mut copy = left.clone();
+        widen(&mut mono, &mut copy, 0.0, 1000.0);
+        assert_eq!(mono, copy);
+        assert_eq!(mono, left);
+    }
+
+    #[test]
+    fn reverb_turns_click_into_decaying_tail() {
+        let mut wave = vec![0.0; 100];
+        wave[0] = 1.0;
+        reverb(&mut wave, 0.8, 0.5, 1.0, 8000.0);
+        assert!(wave.len() > 100);
+        let tail = &wave[100..];
+        let early = rms(&tail[..tail.len() / 4]);
+        let late = rms(&tail[tail.len() * 3 / 4..]);
+        assert!(early > 0.0);
+        assert!(late < early);
+        let nonzero = tail.iter().filter(|sample| sample.abs() > 1e-6).count();
+        assert!(nonzero > tail.len() / 2);
+        assert!(wave.iter().all(|sample| sample.is_finite()));
+    }
+
+    #[test]
+    fn compressor_reduces_loud_transient_over_attack() {
+        let sample_rate = 8000.0;
+        let mut wave = sine_wave(200.0, sample_rate, 4000);
+        for sample in wave[..2000].iter_mut() {
+            *sample *= 0.1;
+        }
+        let original = wave.clone();
+        let mut transparent = wave.clone();
+        compress(&mut transparent, 0.2, 1.0, 5.0, 50.0, sample_rate);
+        assert_eq!(transparent, original);
+        compress(&mut wave, 0.2, 4.0, 10.0, 50.0, sample_rate);
+        assert_eq!(&wave[..2000], &original[..2000]);
+        let onset = rms(&wave[2000..2040]) / rms(&original[2000..2040]);
+        let settled = rms(&wave[3000..4000]) / rms(&original[3000..4000]);
+        assert!(settled < 0.6);
+        assert!(onset > settled);
+    }
+
+    #[test]
+    fn reports_exact_clipping_indices() {
+        let mut wave = sine_wave(100.0, 8000.0, 200);
+        for sample in wave.iter_mut() {
+            *sample *= 0.9;
+        }
+        wave[17] = 1.2;
+        wave[150] = -1.05;
+        assert_eq!(detect_clipping(&wave, 1.0), vec![17, 150]);
+        assert!(detect_clipping(&wave, 2.0).is_empty());
+    }
+
+    #[test]
+    fn rms_normalization_equalizes_waveforms() {
+        let mut sine = sine_wave(220.0, 8000.0, 8000);
+        let mut square: Vec<f32> = sine.iter().map(|sample| 0.3 * sample.signum()).collect();
+        normalize_rms(&mut sine, 0.25);
+        normalize_rms(&mut square, 0.25);
+        assert!((rms(&sine) - 0.25).abs() < 1e-4);
+        assert!((rms(&square) - 0.25).abs() < 1e-4);
+        let mut silent = vec![0.0; 100];
+        normalize_rms(&mut silent, 0.25);
+        assert!(silent.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn gain_curve_ramps_linearly() {
+        let mut wave = vec![1.0; 2000];
+        apply_gain_curve(&mut wave, &[(0.0, 0.0), (1.0, 1.0)], 1000.0);
+        for (index, sample) in wave[..1000].iter().enumerate() {
+            assert!((sample - index as f32 / 1000.0).abs() < 1e-5);
+        }
+        assert!(wave[1000..].iter().all(|&sample| sample == 1.0));
+    }
+
+    #[test]
+    fn trims_silent_padding_only() {
+        let mut wave = vec![0.0; 50];
+        wave.extend([0.5, 0.0, 0.0, -0.5]);
+        wave.extend(vec![0.001; 30]);
+        trim_silence(&mut wave, 0.01);
+        assert_eq!(wave, vec![0.5, 0.0, 0.0, -0.5]);
+        let mut silent = vec![0.001; 100];
+        trim_silence(&mut silent, 0.01);
+        assert!(silent.is_empty());
+    }
+
+    #[test]
+    fn stutter_repeats_source_slice() {
+        let original: Vec<f32> = (0..1000).map(|index| index as f32).collect();
+        let mut wave = original.clone();
+        stutter(&mut wave, 0.1, 0.05, 3, 1000.0);
+        assert_eq!(&wave[..150], &original[..150]);
+        for repeat in 1..=3 {
+            let offset = 100 + repeat * 50;
+            assert_eq!(&wave[offset..offset + 50], &original[100..150]);
+        }
+        assert_eq!(&wave[300..], &original[300..]);
+        let mut short = original[..180].to_vec();
+        stutter(&mut short, 0.1, 0.05, 4, 1000.0);
+        assert_eq!(short.len(), 180);
+        assert_eq!(&short[150..], &original[100..130]);
+    }
+
+    #[test]
+    fn gate_silences_off_portions() {
+        let mut wave = vec![1.0; 8000];
+        gate(&mut wave, 4.0, 0.5, 8000.0);
+        for pulse in 0..4 {
+            let start = pulse * 2000;
+            assert!(wave[start + 20..start + 980]
+                .iter()
+                .all(|&sample| sample == 1.0));
+            assert!(wave[start + 1000..start + 2000]
+                .iter()
+                .all(|&sample| sample == 0.0));
+            assert!(wave[start] < 0.1 && wave[start + 8] > 0.0 && wave[start + 8] < 1.0);
+        }
+    }
+
+    #[test]
+    fn octave_shift_halves_period() {
+        let rising_crossings = |wave: &[f32]| {
+            wave.windows(2)
+                .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+                .count()
+        };
+        let wave = sine_wave(100.0, 8000.0, 8000);
+        let shifted = pitch_shift(&wave, 12.0, 8000.0);
+        assert_eq!(shifted.len(), 4000);
+        assert!(rising_crossings(&shifted).abs_diff(rising_crossings(&wave)) <= 1);
+        let period = |wave: &[f32]| wave.len() as f32 / rising_crossings(wave) as f32;
+        assert!((period(&shifted) - period(&wave) / 2.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn extreme_pitch_shifts_stay_bounded() {
+        let wave = vec![0.1; 10];
+        let down = pitch_shift(&wave, -1000.0, 8000.0);
+        assert_eq!(down.len(), 10 << 10);
+        assert!(down.iter().all(|&sample| sample == 0.1));
+        assert!(pitch_shift(&wave, 1000.0, 8000.0).is_empty());
+        assert!(pitch_shift(&wave, f32::NAN, 8000.0).is_empty());
+        assert_eq!(pitch_shift(&wave, -12.0, 1e-4).len(), 8);
+    }
+
+    #[test]
+    fn time_stretch_keeps_pitch() {
+        let rising_crossings = |wave: &[f32]| {
+            wave.windows(2)
+                .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+                .count() as f32
+        };
+        let wave = sine_wave(200.0, 8000.0, 8000);
+        for factor in [0.5, 2.0] {
+            let stretched = time_stretch(&wave, factor, 8000.0);
+            let expected = (8000.0 * factor) as usize;
+            assert!(
+                stretched.len().abs_diff(expected) < 200,
+                "{}",
+                stretched.len()
+            );
+            let frequency = rising_crossings(&stretched) * 8000.0 / stretched.len() as f32;
+            assert!((frequency - 200.0).abs() < 10.0, "frequency {}", frequency);
+        }
+    }
+
+    #[test]
+    fn chorus_modulates_and_zero_mix_is_dry() {
+        let original = sine_wave(440.0, 8000.0, 4000);
+        let mut dry = original.clone();
+        chorus(&mut dry, 1.0, 5.0, 0.0, 8000.0);
+        assert_eq!(dry, original);
+        let mut wet = original.clone();
+        chorus(&mut wet, 1.0, 5.0, 0.5, 8000.0);
+        assert_eq!(wet.len(), original.len());
+        let difference: Vec<f32> = wet
+            .iter()
+            .zip(&original)
+            .map(|(wet, dry)| wet - dry)
+            .collect();
+        assert!(rms(&difference) > 0.05);
+        assert!(wet.iter().all(|sample| sample.abs() <= 1.0 + 1e-6));
+    }
+
+    #[test]
+    fn ping_pong_bounces_first_echo_right() {
+        let mut left = vec![0.0; 100];
+        let mut right = vec![0.0; 100];
+        left[0] = 1.0;
+        ping_pong_delay(&mut left, &mut right, 0.01, 0.5, 1000.0);
+        assert_eq!(left.len(), right.len());
+        assert!((right[10] - 0.5).abs() < 1e-6);
+        assert_eq!(left[10], 0.0);
+        assert!((left[20] - 0.25).abs() < 1e-6);
+        assert_eq!(right[20], 0.0);
+        assert!((right[30] - 0.125).abs() < 1e-6);
+    }
+
+    #[test]
+    fn analyze_matches_hand_computed_stats() {
+        let stats = analyze(&[0.5, -1.5, 1.0, 2.0]);
+        assert_eq!(stats.peak, 2.0);
+        assert!((stats.rms - (7.5f32 / 4.0).sqrt()).abs() < 1e-6);
+        assert!((stats.dc_offset - 0.5).abs() < 1e-6);
+        assert_eq!(stats.clip_count, 2);
+        let empty = analyze(&[]);
+        assert_eq!((empty.peak, empty.rms, empty.clip_count), (0.0, 0.0, 0));
+    }
+
+    #[test]
+    fn spectrum_peaks_at_sine_frequency() {
+        let wave = sine_wave(1000.0, 8192.0, 4096);
+        let bins = spectrum(&wave, 0, 1024, 8192.0);
+        assert_eq!(bins.len(), 513);
+        let (frequency, magnitude) =
+            bins.iter().copied().fold(
+                (0.0, 0.0),
+                |best, bin| if bin.1 > best.1 { bin } else { best },
+            );
+        assert_eq!(frequency, 1000.0);
+        assert!((magnitude - 1.0).abs() < 0.01);
+        assert!(bins
+            .iter()
+            .filter(|(bin, _)| (bin - 1000.0).abs() > 50.0)
+            .all(|(_, other)| *other < 0.01));
+    }
+
+    #[test]
+    fn convolution_with_simple_impulses() {
+        let original = sine_wave(300.0, 8000.0, 700);
+        let mut unit = original.clone();
+        convolve(&mut unit, &[1.0]);
+        assert_eq!(unit.len(), original.len());
+        for (convolved, dry) in unit.iter().zip(&original) {
+            assert!((convolved - dry).abs() < 1e-5);
+        }
+        let mut two_tap = original.clone();
+        convolve(&mut two_tap, &[0.5, 0.0, 0.0, 0.25]);
+        assert_eq!(two_tap.len(), original.len() + 3);
+        for (index, convolved) in two_tap.iter().enumerate() {
+            let direct = original.get(index).map_or(0.0, |sample| 0.5 * sample);
+            let delayed = index
+                .checked_sub(3)
+                .and_then(|source| original.get(source))
+                .map_or(0.0, |sample| 0.25 * sample);
+            assert!((convolved - direct - delayed).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn soft_clip_rounds_peaks_smoothly() {
+        let inputs: Vec<f32> = (-40..=40).map(|step| step as f32 / 20.0).collect();
+        let mut wave = inputs.clone();
+        soft_clip(&mut wave);
+        for (input, output) in inputs.iter().zip(&wave) {
+            if input.abs() <= 0.5 {
+                assert!((output - input).abs() < 0.02, "{} -> {}", input, output);
+            }
+            assert!(output.abs() <= 1.0);
+        }
+        assert!(wave.windows(2).all(|pair| pair[1] >= pair[0]));
+        let largest_step = wave
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .fold(0.0, f32::max);
+        assert!(largest_step < 0.06);
+        assert!(wave[80] > 0.99 && wave[80] < 1.0);
+        assert!(wave[0] < -0.99 && wave[0] > -1.0);
+    }
+
+    #[test]
+    fn resampling_keeps_frequency_and_scales_length() {
+        let rising_crossings = |wave: &[f32]| {
+            wave.windows(2)
+                .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+                .count() as f32
+        };
+        let wave = sine_wave(1000.0, 48000.0, 48000);
+        let resampled = resample(&wave, 48000, 16000);
+        assert!(resampled.len().abs_diff(16000) <= 1);
+        let frequency = rising_crossings(&resampled) * 16000.0 / resampled.len() as f32;
+        assert!((frequency - 1000.0).abs() < 5.0, "frequency {}", frequency);
+        let cd_rate = resample(&wave[..4800], 48000, 44100);
+        assert!(cd_rate.len().abs_diff(4410) <= 1);
+    }
+
+    #[test]
+    fn envelope_rises_over_attack_time() {
+        let mut step = vec![0.0; 100];
+        step.extend(vec![1.0; 900]);
+        let envelope = envelope_follower(&step, 10.0, 50.0, 1000.0);
+        assert!(envelope[..100].iter().all(|&level| level == 0.0));
+        // A one-pole follower covers 1 - 1/e of the step after one time constant.
+        assert!((envelope[109] - (1.0 - (-1.0f32).exp())).abs() < 0.01);
+        assert!(envelope[100..].windows(2).all(|pair| pair[1] >= pair[0]));
+        assert!(envelope[200] > 0.99);
+        let instant = envelope_follower(&step, 0.0, 50.0, 1000.0);
+        assert_eq!(instant[100], 1.0);
+    }
+}
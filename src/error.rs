@@ -0,0 +1,114 @@
+use std::fmt;
+
+pub const MIN_SAMPLE_RATE: u32 = 8000;
+pub const MAX_SAMPLE_RATE: u32 = 192_000;
+/// Longest render, in seconds, including trailing silence.
+pub const MAX_RENDER_SECONDS: f32 = 86_400.0;
+
+#[derive(Debug)]
+pub enum RenderError {
+    InvalidSampleRate(u32),
+    InvalidTime {
+        index: usize,
+        time: f32,
+    },
+    InvalidDuration {
+        index: usize,
+        duration: f32,
+    },
+    InvalidSilenceTime(f32),
+    InvalidRange {
+        start: f32,
+        end: f32,
+    },
+    TooLong(f32),
+    #[cfg(feature = "fs")]
+    Wav(hound::Error),
+    #[cfg(feature = "fs")]
+    Io(std::io::Error),
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::InvalidSampleRate(sample_rate) => write!(
+                f,
+                "sample rate {} is outside {}..={} Hz",
+                sample_rate, MIN_SAMPLE_RATE, MAX_SAMPLE_RATE
+            ),
+            RenderError::InvalidTime { index, time } => {
+                write!(f, "element {} starts at invalid time {}", index, time)
+            }
+            RenderError::InvalidDuration { index, duration } => {
+                write!(f, "element {} has invalid duration {}", index, duration)
+            }
+            RenderError::InvalidSilenceTime(silence_time) => {
+                write!(f, "invalid silence time {}", silence_time)
+            }
+            RenderError::InvalidRange { start, end } => {
+                write!(f, "invalid render range {}..{}", start, end)
+            }
+            RenderError::TooLong(seconds) => write!(
+                f,
+                "render length {} s exceeds {} s",
+                seconds, MAX_RENDER_SECONDS
+            ),
+            #[cfg(feature = "fs")]
+            RenderError::Wav(error) => write!(f, "{}", error),
+            #[cfg(feature = "fs")]
+            RenderError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+#[cfg(feature = "fs")]
+impl From<hound::Error> for RenderError {
+    fn from(error: hound::Error) -> RenderError {
+        RenderError::Wav(error)
+    }
+}
+
+#[cfg(feature = "fs")]
+impl From<std::io::Error> for RenderError {
+    fn from(error: std::io::Error) -> RenderError {
+        RenderError::Io(error)
+    }
+}
+
+pub fn check_sample_rate(sample_rate: u32) -> Result<(), RenderError> {
+    if (MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+        Ok(())
+    } else {
+        Err(RenderError::InvalidSampleRate(sample_rate))
+    }
+}
+
+pub fn check_render_length(seconds: f32) -> Result<(), RenderError> {
+    if seconds <= MAX_RENDER_SECONDS {
+        Ok(())
+    } else {
+        Err(RenderError::TooLong(seconds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Generator, WaveFunction};
+
+    #[test]
+    fn zero_sample_rate_is_an_error() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 1.0, 0.0, 1.0);
+        for sample_rate in [0, MIN_SAMPLE_RATE - 1, MAX_SAMPLE_RATE + 1, u32::MAX] {
+            assert!(matches!(
+                generator.render_to_buffer(sample_rate, 0.0),
+                Err(RenderError::InvalidSampleRate(rate)) if rate == sample_rate
+            ));
+        }
+        assert!(check_sample_rate(MIN_SAMPLE_RATE).is_ok());
+        assert!(check_sample_rate(MAX_SAMPLE_RATE).is_ok());
+    }
+}
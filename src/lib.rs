@@ -0,0 +1,3336 @@
+pub mod composition;
+pub mod effects;
+pub mod error;
+#[cfg(feature = "fs")]
+pub mod midi;
+pub mod mixer;
+pub mod oscillator;
+pub mod pitch;
+#[cfg(feature = "playback")]
+pub mod playback;
+pub mod rng;
+pub mod scala;
+pub mod tempo;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use error::RenderError;
+use oscillator::{apply_wave_function, sine, Interpolation, OscillatorState};
+use pitch::{note_from_frequency, Pitch, Scale, Tuning};
+use rng::Rng;
+use tempo::{Tempo, TimeSignature};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum WaveFunction {
+    Square,
+    Sawtooth,
+    Triangle,
+    Sine,
+    SquareBandLimited,
+    SawtoothBandLimited,
+    WhiteNoise,
+    PinkNoise,
+    #[serde(skip)]
+    Custom(Arc<dyn Fn(f32, f32) -> f32 + Send + Sync>),
+    Rest,
+    Sample {
+        data: Arc<Vec<f32>>,
+        sample_rate: u32,
+        #[serde(default)]
+        interpolation: Interpolation,
+    },
+    Pulse {
+        duty: f32,
+    },
+    Fm {
+        ratio: f32,
+        index: f32,
+    },
+    Additive {
+        harmonics: Vec<f32>,
+    },
+    Pluck,
+    Morph {
+        from: Box<WaveFunction>,
+        to: Box<WaveFunction>,
+    },
+}
+
+impl WaveFunction {
+    #[cfg(feature = "fs")]
+    pub fn from_wav(file_name: &str) -> Result<WaveFunction, hound::Error> {
+        let mut reader = hound::WavReader::open(file_name)?;
+        let spec = reader.spec();
+        let samples: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 / scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+        let channels = spec.channels as usize;
+        let data = samples
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+        Ok(WaveFunction::Sample {
+            data: Arc::new(data),
+            sample_rate: spec.sample_rate,
+            interpolation: Interpolation::default(),
+        })
+    }
+    pub fn reference_gain(&self) -> f32 {
+        match self {
+            WaveFunction::Square | WaveFunction::SquareBandLimited => 0.5,
+            WaveFunction::Pulse { .. } => 0.5,
+            WaveFunction::Sawtooth | WaveFunction::SawtoothBandLimited => 0.6,
+            WaveFunction::WhiteNoise => 0.5,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum BitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl BitDepth {
+    #[cfg(feature = "fs")]
+    pub fn wav_spec(&self, sample_rate: u32, channels: u16) -> hound::WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            BitDepth::Int16 => (16, hound::SampleFormat::Int),
+            BitDepth::Int24 => (24, hound::SampleFormat::Int),
+            BitDepth::Float32 => (32, hound::SampleFormat::Float),
+        };
+        hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RenderSettings {
+    pub sample_rate: u32,
+    pub silence_time: f32,
+    pub bit_depth: BitDepth,
+}
+
+pub enum PcmFormat {
+    Int16,
+    Float32,
+}
+
+impl PcmFormat {
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            PcmFormat::Int16 => 2,
+            PcmFormat::Float32 => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum EnvelopeCurve {
+    #[default]
+    Linear,
+    Exponential,
+    Cosine,
+}
+
+impl EnvelopeCurve {
+    pub fn shape(&self, progress: f32) -> f32 {
+        let progress = progress.clamp(0.0, 1.0);
+        match self {
+            EnvelopeCurve::Linear => progress,
+            EnvelopeCurve::Exponential => (5.0 * progress).exp_m1() / 5.0f32.exp_m1(),
+            EnvelopeCurve::Cosine => 0.5 - 0.5 * (std::f32::consts::PI * progress).cos(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum VelocityCurve {
+    #[default]
+    Linear,
+    Exponential,
+    Logarithmic,
+}
+
+pub fn map_velocity(velocity: f32, curve: VelocityCurve) -> f32 {
+    let velocity = velocity.clamp(0.0, 1.0);
+    match curve {
+        VelocityCurve::Linear => velocity,
+        VelocityCurve::Exponential => (4.0 * velocity).exp_m1() / 4.0f32.exp_m1(),
+        VelocityCurve::Logarithmic => (1.0 + 9.0 * velocity).log10(),
+    }
+}
+
+/// How overlapping elements combine: `Average` divides by the number of sounding
+/// elements at each sample and `Max` keeps the loudest one.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum MixMode {
+    #[default]
+    Sum,
+    Average,
+    Max,
+}
+
+impl MixMode {
+    pub fn combine(&self, mixed: f32, level: f32) -> f32 {
+        match self {
+            MixMode::Sum | MixMode::Average => mixed + level,
+            MixMode::Max if level.abs() > mixed.abs() => level,
+            MixMode::Max => mixed,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+    #[serde(default)]
+    pub curve: EnvelopeCurve,
+}
+
+impl Envelope {
+    pub fn new(attack: f32, decay: f32, sustain: f32, release: f32) -> Envelope {
+        Envelope {
+            attack,
+            decay,
+            sustain,
+            release,
+            curve: EnvelopeCurve::Linear,
+        }
+    }
+    pub fn held_gain(&self, t: f32) -> f32 {
+        if t < self.attack {
+            self.curve.shape(t / self.attack)
+        } else if t < self.attack + self.decay {
+            1.0 - (1.0 - self.sustain) * (t - self.attack) / self.decay
+        } else {
+            self.sustain
+        }
+    }
+    pub fn gain(&self, t: f32, duration: f32) -> f32 {
+        if t < duration {
+            return self.held_gain(t);
+        }
+        let released = t - duration;
+        if released >= self.release {
+            return 0.0;
+        }
+        self.held_gain(duration) * self.curve.shape(1.0 - released / self.release)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MusicElement {
+    pub function: WaveFunction,
+    pub time: f32,
+    pub duration: f32,
+    pub pitch: Pitch,
+    pub amplitude: f32,
+    #[serde(default)]
+    pub envelope: Option<Envelope>,
+    #[serde(default)]
+    pub pan: f32,
+    #[serde(default)]
+    pub pan_end: Option<f32>,
+    #[serde(default)]
+    pub end_note: Option<f32>,
+    #[serde(default)]
+    pub vibrato_rate: f32,
+    #[serde(default)]
+    pub vibrato_depth: f32,
+    #[serde(default)]
+    pub tremolo_rate: f32,
+    #[serde(default)]
+    pub tremolo_depth: f32,
+    #[serde(default)]
+    pub loop_sample: bool,
+    #[serde(default)]
+    pub detune_cents: f32,
+    #[serde(default)]
+    pub ring_mod_hz: Option<f32>,
+    #[serde(default)]
+    pub velocity: Option<f32>,
+    #[serde(default)]
+    pub velocity_curve: VelocityCurve,
+    #[serde(default = "default_reset_phase")]
+    pub reset_phase: bool,
+    #[serde(default)]
+    pub bus: Option<String>,
+    /// Raised-cosine fade applied to both ends of this element only.
+    #[serde(default)]
+    pub fade_ms: f32,
+    /// Starting phase in cycles, added on top of whatever `reset_phase` gives.
+    #[serde(default)]
+    pub phase_offset: f32,
+    /// Chance from 0 to 1 that the element sounds, drawn from `Generator::seed` at render.
+    #[serde(default = "default_probability")]
+    pub probability: f32,
+    /// One-pole low-pass cutoff in Hz, swept by the envelope through `filter_env_amount`.
+    #[serde(default)]
+    pub filter_cutoff: Option<f32>,
+    #[serde(default)]
+    pub filter_env_amount: f32,
+}
+
+fn default_reset_phase() -> bool {
+    true
+}
+
+fn default_probability() -> f32 {
+    1.0
+}
+
+impl MusicElement {
+    pub fn new(
+        function: WaveFunction,
+        time: f32,
+        duration: f32,
+        note: f32,
+        amplitude: f32,
+    ) -> MusicElement {
+        MusicElement {
+            function,
+            time,
+            duration,
+            pitch: Pitch::Note(note),
+            amplitude,
+            envelope: None,
+            pan: 0.0,
+            pan_end: None,
+            end_note: None,
+            vibrato_rate: 0.0,
+            vibrato_depth: 0.0,
+            tremolo_rate: 0.0,
+            tremolo_depth: 0.0,
+            loop_sample: false,
+            detune_cents: 0.0,
+            ring_mod_hz: None,
+            velocity: None,
+            velocity_curve: VelocityCurve::Linear,
+            reset_phase: true,
+            bus: None,
+            fade_ms: 0.0,
+            phase_offset: 0.0,
+            probability: 1.0,
+            filter_cutoff: None,
+            filter_env_amount: 0.0,
+        }
+    }
+    pub fn bend_at(&self, t: f32, start_note: f32) -> f32 {
+        let mut bend = 0.0;
+        if let Some(end_note) = self.end_note {
+            if self.duration > 0.0 {
+                let progress = (t / self.duration).min(1.0);
+                bend += (end_note - start_note) * progress;
+            }
+        }
+        if self.vibrato_depth != 0.0 {
+            bend += self.vibrato_depth * sine(self.vibrato_rate * t);
+        }
+        bend
+    }
+    pub fn frequency_at(&self, t: f32, tuning: &Tuning, reference: f32) -> f32 {
+        let frequency = match self.pitch {
+            Pitch::Note(note) => tuning.frequency(note + self.bend_at(t, note), reference),
+            Pitch::Hz(frequency) => {
+                let start_note = note_from_frequency(frequency, reference);
+                frequency * 2.0f32.powf(self.bend_at(t, start_note) / 12.0)
+            }
+        };
+        frequency * 2.0f32.powf(self.detune_cents / 1200.0)
+    }
+    pub fn gain_at(&self, t: f32) -> f32 {
+        let mut gain = self.amplitude;
+        let velocity = self.velocity.map(|velocity| velocity.clamp(0.0, 1.0));
+        if let Some(velocity) = velocity {
+            gain *= map_velocity(velocity, self.velocity_curve);
+        }
+        if let Some(envelope) = &self.envelope {
+            gain *= match velocity {
+                Some(velocity) => Envelope {
+                    attack: envelope.attack * (1.0 - 0.5 * velocity),
+                    ..envelope.clone()
+                }
+                .gain(t, self.duration),
+                None => envelope.gain(t, self.duration),
+            };
+        }
+        if self.tremolo_depth != 0.0 {
+            let cycle = 2.0 * std::f32::consts::PI * self.tremolo_rate * t;
+            gain *= 1.0 - self.tremolo_depth * (0.5 - 0.5 * cycle.cos());
+        }
+        match self.ring_mod_hz {
+            Some(ring_mod_hz) if ring_mod_hz != 0.0 => gain * sine(ring_mod_hz * t),
+            _ => gain,
+        }
+    }
+    /// The cutoff moves `filter_env_amount` octaves at full envelope level; without an
+    /// envelope the level is held at 1.
+    pub fn filter_cutoff_at(&self, t: f32) -> Option<f32> {
+        let level = self
+            .envelope
+            .as_ref()
+            .map_or(1.0, |envelope| envelope.gain(t, self.duration));
+        self.filter_cutoff
+            .map(|cutoff| cutoff * 2.0f32.powf(self.filter_env_amount * level))
+    }
+    pub fn release_time(&self) -> f32 {
+        self.envelope
+            .as_ref()
+            .map_or(0.0, |envelope| envelope.release)
+    }
+    pub fn end_time(&self) -> f32 {
+        self.time + self.duration + self.release_time()
+    }
+    pub fn pan_at(&self, t: f32) -> f32 {
+        match self.pan_end {
+            Some(pan_end) if self.duration > 0.0 => {
+                let progress = (t / self.duration).min(1.0);
+                self.pan + (pan_end - self.pan) * progress
+            }
+            _ => self.pan,
+        }
+    }
+    pub fn pan_gains(&self, t: f32) -> (f32, f32) {
+        let angle = (self.pan_at(t).clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        (angle.cos(), angle.sin())
+    }
+}
+
+pub struct MusicElementBuilder {
+    element: MusicElement,
+}
+
+impl MusicElementBuilder {
+    pub fn new() -> MusicElementBuilder {
+        MusicElementBuilder {
+            element: MusicElement::new(WaveFunction::Sine, 0.0, 1.0, 0.0, 1.0),
+        }
+    }
+    pub fn function(mut self, function: WaveFunction) -> MusicElementBuilder {
+        self.element.function = function;
+        self
+    }
+    pub fn time(mut self, time: f32) -> MusicElementBuilder {
+        self.element.time = time;
+        self
+    }
+    pub fn duration(mut self, duration: f32) -> MusicElementBuilder {
+        self.element.duration = duration;
+        self
+    }
+    pub fn note(mut self, note: f32) -> MusicElementBuilder {
+        self.element.pitch = Pitch::Note(note);
+        self
+    }
+    pub fn hz(mut self, frequency_hz: f32) -> MusicElementBuilder {
+        self.element.pitch = Pitch::Hz(frequency_hz);
+        self
+    }
+    pub fn amplitude(mut self, amplitude: f32) -> MusicElementBuilder {
+        self.element.amplitude = amplitude;
+        self
+    }
+    pub fn envelope(mut self, envelope: Envelope) -> MusicElementBuilder {
+        self.element.envelope = Some(envelope);
+        self
+    }
+    pub fn pan(mut self, pan: f32) -> MusicElementBuilder {
+        self.element.pan = pan;
+        self
+    }
+    pub fn pan_end(mut self, pan_end: f32) -> MusicElementBuilder {
+        self.element.pan_end = Some(pan_end);
+        self
+    }
+    pub fn end_note(mut self, end_note: f32) -> MusicElementBuilder {
+        self.element.end_note = Some(end_note);
+        self
+    }
+    pub fn vibrato(mut self, rate: f32, depth: f32) -> MusicElementBuilder {
+        self.element.vibrato_rate = rate;
+        self.element.vibrato_depth = depth;
+        self
+    }
+    pub fn tremolo(mut self, rate: f32, depth: f32) -> MusicElementBuilder {
+        self.element.tremolo_rate = rate;
+        self.element.tremolo_depth = depth;
+        self
+    }
+    pub fn loop_sample(mut self, loop_sample: bool) -> MusicElementBuilder {
+        self.element.loop_sample = loop_sample;
+        self
+    }
+    pub fn detune_cents(mut self, detune_cents: f32) -> MusicElementBuilder {
+        self.element.detune_cents = detune_cents;
+        self
+    }
+    pub fn ring_mod_hz(mut self, ring_mod_hz: f32) -> MusicElementBuilder {
+        self.element.ring_mod_hz = Some(ring_mod_hz);
+        self
+    }
+    pub fn velocity(mut self, velocity: f32) -> MusicElementBuilder {
+        self.element.velocity = Some(velocity);
+        self
+    }
+    pub fn velocity_curve(mut self, velocity_curve: VelocityCurve) -> MusicElementBuilder {
+        self.element.velocity_curve = velocity_curve;
+        self
+    }
+    pub fn reset_phase(mut self, reset_phase: bool) -> MusicElementBuilder {
+        self.element.reset_phase = reset_phase;
+        self
+    }
+    pub fn bus(mut self, bus: &str) -> MusicElementBuilder {
+        self.element.bus = Some(bus.to_string());
+        self
+    }
+    pub fn fade_ms(mut self, fade_ms: f32) -> MusicElementBuilder {
+        self.element.fade_ms = fade_ms;
+        self
+    }
+    pub fn phase_offset(mut self, phase_offset: f32) -> MusicElementBuilder {
+        self.element.phase_offset = phase_offset;
+        self
+    }
+    pub fn probability(mut self, probability: f32) -> MusicElementBuilder {
+        self.element.probability = probability;
+        self
+    }
+    pub fn filter(mut self, cutoff: f32, env_amount: f32) -> MusicElementBuilder {
+        self.element.filter_cutoff = Some(cutoff);
+        self.element.filter_env_amount = env_amount;
+        self
+    }
+    pub fn build(self) -> MusicElement {
+        self.element
+    }
+}
+
+impl Default for MusicElementBuilder {
+    fn default() -> MusicElementBuilder {
+        MusicElementBuilder::new()
+    }
+}
+
+pub const MAIN_BUS: &str = "main";
+
+#[derive(Serialize)]
+pub struct NoteEvent {
+    pub time: f32,
+    pub duration: f32,
+    pub pitch: Pitch,
+    pub amplitude: f32,
+}
+
+#[derive(Serialize)]
+pub struct Metadata {
+    pub total_duration: f32,
+    pub element_count: usize,
+    pub tempo_bpm: Option<f32>,
+    pub notes: Vec<NoteEvent>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Generator {
+    pub elements: Vec<MusicElement>,
+    pub normalize_peak: Option<f32>,
+    pub tempo: Option<Tempo>,
+    pub time_signature: Option<TimeSignature>,
+    pub stereo: bool,
+    pub upmix: bool,
+    pub max_voices: Option<usize>,
+    /// Monophonic: each note cuts off the one before it, as `max_voices` of 1 would.
+    pub mono: bool,
+    pub seed: u64,
+    pub tuning_reference: f32,
+    pub tuning: Tuning,
+    pub loudness_compensation: bool,
+    pub click_reduction_ms: f32,
+    pub master_gain: f32,
+    pub dither: DitherMode,
+    pub pitch_bend: Vec<(f32, f32)>,
+    pub mix_mode: MixMode,
+}
+
+pub enum ArpPattern {
+    Up,
+    Down,
+    UpDown,
+}
+
+impl ArpPattern {
+    pub fn order(&self, notes: &[f32]) -> Vec<f32> {
+        match self {
+            ArpPattern::Up => notes.to_vec(),
+            ArpPattern::Down => notes.iter().rev().copied().collect(),
+            ArpPattern::UpDown => {
+                let mut order = notes.to_vec();
+                if notes.len() > 2 {
+                    order.extend(notes[1..notes.len() - 1].iter().rev());
+                }
+                order
+            }
+        }
+    }
+}
+
+struct Voice<'a> {
+    generator: &'a Generator,
+    element: &'a MusicElement,
+    sample_rate: f32,
+    first_sample: usize,
+    last_sample: usize,
+    rendered: usize,
+    state: OscillatorState,
+    reference_gain: f32,
+    fade_samples: f32,
+    filtered: f32,
+}
+
+impl<'a> Voice<'a> {
+    fn new(
+        generator: &'a Generator,
+        index: usize,
+        element: &'a MusicElement,
+        sample_rate: f32,
+        (first_sample, last_sample): (usize, usize),
+    ) -> Voice<'a> {
+        let reference_gain = if generator.loudness_compensation {
+            element.function.reference_gain()
+        } else {
+            1.0
+        };
+        let mut state = OscillatorState::new(generator.seed.wrapping_add(index as u64));
+        if !element.reset_phase {
+            let frequency =
+                element.frequency_at(0.0, &generator.tuning, generator.tuning_reference);
+            state.advance(frequency * element.time);
+        }
+        state.advance(element.phase_offset);
+        state.reference = generator.tuning_reference;
+        state.loop_sample = element.loop_sample;
+        Voice {
+            generator,
+            element,
+            sample_rate,
+            first_sample,
+            last_sample,
+            rendered: 0,
+            state,
+            reference_gain,
+            fade_samples: generator.click_reduction_ms * sample_rate / 1000.0,
+            filtered: 0.0,
+        }
+    }
+    fn next_level(&mut self) -> f32 {
+        let element = self.element;
+        let generator = self.generator;
+        let sample_rate = self.sample_rate;
+        let t = self.rendered as f32 / sample_rate;
+        let mut frequency = element.frequency_at(t, &generator.tuning, generator.tuning_reference);
+        let bend = generator.pitch_bend_at(element.time + t);
+        if bend != 0.0 {
+            frequency *= 2.0f32.powf(bend / 12.0);
+        }
+        if element.duration > 0.0 {
+            self.state.progress = (t / element.duration).min(1.0);
+        }
+        let mut level = apply_wave_function(
+            &element.function,
+            t,
+            frequency,
+            sample_rate,
+            &mut self.state,
+        );
+        if let Some(cutoff) = element.filter_cutoff_at(t) {
+            let cutoff = cutoff.clamp(0.0, sample_rate / 2.0);
+            let alpha = 1.0 - (-2.0 * std::f32::consts::PI * cutoff / sample_rate).exp();
+            self.filtered += alpha * (level - self.filtered);
+            level = self.filtered;
+        }
+        level *= element.gain_at(t) * self.reference_gain * generator.master_gain;
+        let sounding_samples = self.last_sample.saturating_sub(self.first_sample);
+        let from_edge = (self.rendered as f32).min((sounding_samples - 1 - self.rendered) as f32);
+        if self.fade_samples > 0.0 {
+            level *= (from_edge / self.fade_samples).min(1.0);
+        }
+        let element_fade_samples = element.fade_ms * sample_rate / 1000.0;
+        if from_edge < element_fade_samples {
+            level *= EnvelopeCurve::Cosine.shape(from_edge / element_fade_samples);
+        }
+        self.state.advance(frequency / sample_rate);
+        self.rendered += 1;
+        level
+    }
+}
+
+struct Samples<'a> {
+    generator: &'a Generator,
+    sample_rate: f32,
+    length: usize,
+    position: usize,
+    ranges: Vec<(usize, usize)>,
+    voices: Vec<Option<Voice<'a>>>,
+    gain: Option<f32>,
+}
+
+impl<'a> Samples<'a> {
+    fn new(
+        generator: &'a Generator,
+        sample_rate: u32,
+        length: usize,
+        gain: Option<f32>,
+    ) -> Samples<'a> {
+        let sample_rate = sample_rate as f32;
+        Samples {
+            generator,
+            sample_rate,
+            length,
+            position: 0,
+            ranges: generator.element_ranges(sample_rate, length),
+            voices: generator.elements.iter().map(|_| None).collect(),
+            gain,
+        }
+    }
+}
+
+impl<'a> Iterator for Samples<'a> {
+    type Item = f32;
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.length {
+            return None;
+        }
+        let generator = self.generator;
+        let sample = self.position;
+        let mut level = 0.0;
+        let mut sounding = 0;
+        for (index, element) in generator.elements.iter().enumerate() {
+            let (first_sample, last_sample) = self.ranges[index];
+            if let WaveFunction::Rest = element.function {
+                continue;
+            }
+            if sample < first_sample || sample >= last_sample {
+                continue;
+            }
+            let sample_rate = self.sample_rate;
+            let range = self.ranges[index];
+            let voice = self.voices[index]
+                .get_or_insert_with(|| Voice::new(generator, index, element, sample_rate, range));
+            level = generator.mix_mode.combine(level, voice.next_level());
+            sounding += 1;
+            if sample + 1 == last_sample {
+                self.voices[index] = None;
+            }
+        }
+        self.position += 1;
+        if let MixMode::Average = generator.mix_mode {
+            level /= sounding.max(1) as f32;
+        }
+        Some(match self.gain {
+            Some(gain) => level * gain,
+            None => level,
+        })
+    }
+}
+
+impl Default for Generator {
+    fn default() -> Generator {
+        Generator::new()
+    }
+}
+
+impl Generator {
+    pub fn new() -> Generator {
+        let elements = Vec::new();
+        Generator {
+            elements,
+            normalize_peak: None,
+            tempo: None,
+            time_signature: None,
+            stereo: false,
+            upmix: false,
+            max_voices: None,
+            mono: false,
+            seed: 0,
+            tuning_reference: 440.0,
+            tuning: Tuning::default(),
+            loudness_compensation: false,
+            click_reduction_ms: 5.0,
+            master_gain: 1.0,
+            dither: DitherMode::None,
+            pitch_bend: Vec::new(),
+            mix_mode: MixMode::Sum,
+        }
+    }
+    pub fn with_seed(seed: u64) -> Generator {
+        Generator {
+            seed,
+            ..Generator::new()
+        }
+    }
+    /// Fails if any element uses `WaveFunction::Custom`, which has no serialized form.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+    pub fn from_json(json: &str) -> Result<Generator, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+    pub fn add_music_element(
+        &mut self,
+        function: WaveFunction,
+        time: f32,
+        duration: f32,
+        note: f32,
+        amplitude: f32,
+    ) -> &mut MusicElement {
+        self.add(MusicElement::new(function, time, duration, note, amplitude))
+    }
+    pub fn add(&mut self, element: MusicElement) -> &mut MusicElement {
+        self.elements.push(element);
+        self.elements.last_mut().unwrap()
+    }
+    pub fn remove_element(&mut self, index: usize) -> Option<MusicElement> {
+        if index < self.elements.len() {
+            Some(self.elements.remove(index))
+        } else {
+            None
+        }
+    }
+    pub fn element_count(&self) -> usize {
+        self.elements.len()
+    }
+    pub fn clear(&mut self) {
+        self.elements.clear();
+    }
+    pub fn add_rest(&mut self, time: f32, duration: f32) {
+        self.add_music_element(WaveFunction::Rest, time, duration, 0.0, 0.0);
+    }
+    pub fn add_chord(
+        &mut self,
+        function: WaveFunction,
+        time: f32,
+        duration: f32,
+        notes: &[f32],
+        amplitude: f32,
+    ) {
+        for &note in notes {
+            self.add_music_element(function.clone(), time, duration, note, amplitude);
+        }
+    }
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_arpeggio(
+        &mut self,
+        function: WaveFunction,
+        start_time: f32,
+        note_duration: f32,
+        notes: &[f32],
+        pattern: ArpPattern,
+        repeats: u32,
+        amplitude: f32,
+    ) {
+        let order = pattern.order(notes);
+        let mut time = start_time;
+        for _ in 0..repeats {
+            for &note in order.iter() {
+                self.add_music_element(function.clone(), time, note_duration, note, amplitude);
+                time += note_duration;
+            }
+        }
+    }
+    /// Each hit's amplitude is the previous one's times `amplitude_decay`, starting at 1;
+    /// factors above 1 give a crescendo.
+    pub fn add_roll(
+        &mut self,
+        function: WaveFunction,
+        start_time: f32,
+        total_duration: f32,
+        hits: u32,
+        note: f32,
+        amplitude_decay: f32,
+    ) {
+        let spacing = total_duration / hits.max(1) as f32;
+        let mut amplitude = 1.0;
+        for hit in 0..hits {
+            let time = start_time + hit as f32 * spacing;
+            self.add_music_element(function.clone(), time, spacing, note, amplitude);
+            amplitude *= amplitude_decay;
+        }
+    }
+    /// Voices are detuned evenly across `spread_cents` centred on `note`, and `amplitude`
+    /// is shared between them so the stack peaks no higher than a single voice.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_unison(
+        &mut self,
+        function: WaveFunction,
+        time: f32,
+        duration: f32,
+        note: f32,
+        voices: u32,
+        spread_cents: f32,
+        amplitude: f32,
+    ) {
+        let voice_amplitude = amplitude / voices.max(1) as f32;
+        for voice in 0..voices {
+            let position = if voices > 1 {
+                voice as f32 / (voices - 1) as f32 - 0.5
+            } else {
+                0.0
+            };
+            let element =
+                self.add_music_element(function.clone(), time, duration, note, voice_amplitude);
+            element.detune_cents = position * spread_cents;
+        }
+    }
+    pub fn add_note_at_beat(
+        &mut self,
+        function: WaveFunction,
+        beat: f32,
+        duration_beats: f32,
+        note: f32,
+        amplitude: f32,
+    ) -> &mut MusicElement {
+        let tempo = self.tempo.unwrap_or_default();
+        let time = tempo.beats_to_seconds(beat);
+        let duration = tempo.beats_to_seconds(duration_beats);
+        self.add_music_element(function, time, duration, note, amplitude)
+    }
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_note_at_bar(
+        &mut self,
+        function: WaveFunction,
+        bar: u32,
+        beat: f32,
+        duration_beats: f32,
+        note: f32,
+        amplitude: f32,
+    ) -> &mut MusicElement {
+        let tempo = self.tempo.unwrap_or_default();
+        let time_signature = self.time_signature.unwrap_or_default();
+        let beats = time_signature.quarter_beats(time_signature.bar_to_beats(bar, beat));
+        let time = tempo.beats_to_seconds(beats);
+        let duration = tempo.beats_to_seconds(time_signature.quarter_beats(duration_beats));
+        self.add_music_element(function, time, duration, note, amplitude)
+    }
+    pub fn add_element_hz(
+        &mut self,
+        function: WaveFunction,
+        time: f32,
+        duration: f32,
+        frequency_hz: f32,
+        amplitude: f32,
+    ) -> &mut MusicElement {
+        let element = self.add_music_element(function, time, duration, 0.0, amplitude);
+        element.pitch = Pitch::Hz(frequency_hz);
+        element
+    }
+    pub fn set_all_waveforms(&mut self, function: WaveFunction) {
+        for element in self.elements.iter_mut() {
+            if let WaveFunction::Rest = element.function {
+                continue;
+            }
+            element.function = function.clone();
+        }
+    }
+    pub fn transpose(&mut self, semitones: f32) {
+        for element in self.elements.iter_mut() {
+            element.pitch = element.pitch.transposed(semitones);
+            if let Some(end_note) = element.end_note.as_mut() {
+                *end_note += semitones;
+            }
+        }
+    }
+    /// Rests are kept so the harmony lines up with the original.
+    pub fn harmonize(
+        &self,
+        interval_semitones: f32,
+        function: WaveFunction,
+        amplitude: f32,
+    ) -> Generator {
+        let mut harmony = Generator {
+            elements: self.elements.clone(),
+            tuning: self.tuning.clone(),
+            pitch_bend: self.pitch_bend.clone(),
+            ..*self
+        };
+        harmony.transpose(interval_semitones);
+        harmony.set_all_waveforms(function);
+        for element in harmony.elements.iter_mut() {
+            if !matches!(element.function, WaveFunction::Rest) {
+                element.amplitude = amplitude;
+            }
+        }
+        harmony
+    }
+    /// Only note-numbered pitches move; `Pitch::Hz` elements and rests are left alone.
+    pub fn snap_to_scale(&mut self, root: f32, scale: Scale) {
+        for element in self.elements.iter_mut() {
+            if let WaveFunction::Rest = element.function {
+                continue;
+            }
+            if let Pitch::Note(note) = element.pitch {
+                element.pitch = Pitch::Note(pitch::snap_to_scale(note, root, scale));
+            }
+            if let Some(end_note) = element.end_note.as_mut() {
+                *end_note = pitch::snap_to_scale(*end_note, root, scale);
+            }
+        }
+    }
+    pub fn shift_time(&mut self, offset: f32) {
+        for element in self.elements.iter_mut() {
+            element.time += offset;
+        }
+    }
+    /// Like `shift_time`, but elements pulled before zero start at zero instead.
+    pub fn apply_feel(&mut self, offset_seconds: f32) {
+        for element in self.elements.iter_mut() {
+            element.time = (element.time + offset_seconds).max(0.0);
+        }
+    }
+    /// Element times and lengths are read as beats at `tempo` (120 BPM if unset) and
+    /// re-timed by integrating the `(beat, bpm)` curve. Releases stay in seconds.
+    pub fn apply_tempo_curve(&mut self, points: &[(f32, f32)]) {
+        let tempo = self.tempo.unwrap_or_default();
+        for element in self.elements.iter_mut() {
+            let start_beat = element.time * tempo.bpm / 60.0;
+            let end_beat = (element.time + element.duration) * tempo.bpm / 60.0;
+            let start = tempo::tempo_curve_seconds(points, start_beat);
+            element.duration = tempo::tempo_curve_seconds(points, end_beat) - start;
+            element.time = start;
+        }
+    }
+    pub fn quantize(&mut self, grid_seconds: f32) {
+        if grid_seconds <= 0.0 {
+            return;
+        }
+        for element in self.elements.iter_mut() {
+            element.time = (element.time / grid_seconds).round() * grid_seconds;
+        }
+    }
+    /// Delays elements that start on odd subdivisions by `amount` of a subdivision.
+    pub fn apply_swing(&mut self, subdivision_seconds: f32, amount: f32) {
+        if subdivision_seconds <= 0.0 {
+            return;
+        }
+        let amount = amount.clamp(0.0, 1.0);
+        for element in self.elements.iter_mut() {
+            let position = element.time / subdivision_seconds;
+            let nearest = position.round();
+            if (position - nearest).abs() < 1e-3 && nearest as i64 % 2 != 0 {
+                element.time += amount * subdivision_seconds;
+            }
+        }
+    }
+    /// Each element is moved by the template offset, in fractions of `grid_seconds`, for the
+    /// nearest grid slot; the template repeats every `template.len()` slots.
+    pub fn apply_groove(&mut self, template: &[f32], grid_seconds: f32) {
+        if template.is_empty() || grid_seconds <= 0.0 {
+            return;
+        }
+        for element in self.elements.iter_mut() {
+            let slot = (element.time / grid_seconds).round() as usize;
+            let offset = template[slot % template.len()] * grid_seconds;
+            element.time = (element.time + offset).max(0.0);
+        }
+    }
+    pub fn humanize(&mut self, time_jitter: f32, amp_jitter: f32) {
+        let mut rng = Rng::new(self.seed);
+        for element in self.elements.iter_mut() {
+            element.time = (element.time + rng.next_bipolar() * time_jitter).max(0.0);
+            element.amplitude = (element.amplitude + rng.next_bipolar() * amp_jitter).max(0.0);
+        }
+    }
+    pub fn repeat(&self, count: usize, gap: f32) -> Generator {
+        let length = self.total_duration();
+        let mut repeated = Generator {
+            elements: Vec::with_capacity(self.elements.len() * count),
+            tuning: self.tuning.clone(),
+            pitch_bend: self.pitch_bend.clone(),
+            ..*self
+        };
+        for repetition in 0..count {
+            let offset = repetition as f32 * (length + gap);
+            for element in self.elements.iter() {
+                let mut element = element.clone();
+                element.time += offset;
+                repeated.elements.push(element);
+            }
+        }
+        repeated
+    }
+    pub fn merge(&mut self, other: Generator) {
+        self.elements.extend(other.elements);
+    }
+    pub fn active_at(&self, time: f32) -> Vec<&MusicElement> {
+        self.elements
+            .iter()
+            .filter(|element| element.time <= time && time < element.time + element.duration)
+            .collect()
+    }
+    fn pitch_bend_at(&self, time: f32) -> f32 {
+        effects::interpolate_points(&self.pitch_bend, time)
+    }
+    fn element_range(
+        &self,
+        element: &MusicElement,
+        sample_rate: f32,
+        length: usize,
+    ) -> (usize, usize) {
+        let first_sample = ((sample_rate * element.time) as usize).min(length);
+        let gate_samples = (sample_rate * element.duration) as usize;
+        let release_samples = (sample_rate * element.release_time()) as usize;
+        let last_sample = first_sample
+            .saturating_add(gate_samples)
+            .saturating_add(release_samples);
+        (first_sample, last_sample.min(length))
+    }
+    /// With `max_voices` or `mono`, the oldest sounding element is cut when a new one starts.
+    fn element_ranges(&self, sample_rate: f32, length: usize) -> Vec<(usize, usize)> {
+        // One draw per element in order, so each seed always picks the same subset.
+        let mut trigger = Rng::new(self.seed.rotate_left(32));
+        let mut ranges: Vec<(usize, usize)> = self
+            .elements
+            .iter()
+            .map(|element| {
+                let (first_sample, last_sample) = self.element_range(element, sample_rate, length);
+                if trigger.next_f32() < element.probability {
+                    (first_sample, last_sample)
+                } else {
+                    (first_sample, first_sample)
+                }
+            })
+            .collect();
+        let max_voices = match (self.mono, self.max_voices) {
+            (true, _) => 1,
+            (false, Some(max_voices)) => max_voices,
+            (false, None) => return ranges,
+        };
+        let mut order: Vec<usize> = (0..self.elements.len())
+            .filter(|&index| !matches!(self.elements[index].function, WaveFunction::Rest))
+            .filter(|&index| ranges[index].0 < ranges[index].1)
+            .collect();
+        order.sort_by_key(|&index| ranges[index].0);
+        let mut sounding: Vec<usize> = Vec::new();
+        for index in order {
+            let start = ranges[index].0;
+            sounding.retain(|&other| ranges[other].1 > start);
+            while !sounding.is_empty() && sounding.len() >= max_voices {
+                let oldest = sounding.remove(0);
+                ranges[oldest].1 = start;
+            }
+            if max_voices == 0 {
+                ranges[index].1 = start;
+            } else {
+                sounding.push(index);
+            }
+        }
+        ranges
+    }
+    fn render_element(
+        &self,
+        index: usize,
+        element: &MusicElement,
+        sample_rate: f32,
+        range: (usize, usize),
+        mut output: impl FnMut(usize, f32),
+    ) {
+        if let WaveFunction::Rest = element.function {
+            return;
+        }
+        let mut voice = Voice::new(self, index, element, sample_rate, range);
+        for sample in voice.first_sample..voice.last_sample {
+            output(sample, voice.next_level());
+        }
+    }
+    fn render_elements(&self, sample_rate: f32, wave: &mut [f32], mut progress: impl FnMut(f32)) {
+        let ranges = self.element_ranges(sample_rate, wave.len());
+        for (index, element) in self.elements.iter().enumerate() {
+            self.render_element(
+                index,
+                element,
+                sample_rate,
+                ranges[index],
+                |sample, level| {
+                    wave[sample] = self.mix_mode.combine(wave[sample], level);
+                },
+            );
+            progress((index + 1) as f32 / self.elements.len() as f32);
+        }
+        self.average_voices(wave, &ranges, 0);
+        if self.elements.is_empty() {
+            progress(1.0);
+        }
+    }
+    #[cfg(feature = "parallel")]
+    fn render_elements_parallel(&self, sample_rate: f32, wave: &mut [f32]) {
+        use rayon::prelude::*;
+        let ranges = self.element_ranges(sample_rate, wave.len());
+        let rendered: Vec<(usize, Vec<f32>)> = self
+            .elements
+            .par_iter()
+            .enumerate()
+            .map(|(index, element)| {
+                let mut first_sample = 0;
+                let mut levels = Vec::new();
+                self.render_element(
+                    index,
+                    element,
+                    sample_rate,
+                    ranges[index],
+                    |sample, level| {
+                        if levels.is_empty() {
+                            first_sample = sample;
+                        }
+                        levels.push(level);
+                    },
+                );
+                (first_sample, levels)
+            })
+            .collect();
+        for (first_sample, levels) in rendered {
+            for (sample, level) in wave[first_sample..].iter_mut().zip(levels) {
+                *sample = self.mix_mode.combine(*sample, level);
+            }
+        }
+        self.average_voices(wave, &ranges, 0);
+    }
+    fn render_elements_stereo(&self, sample_rate: f32, left: &mut [f32], right: &mut [f32]) {
+        let ranges = self.element_ranges(sample_rate, left.len());
+        for (index, element) in self.elements.iter().enumerate() {
+            let (first_sample, _) = ranges[index];
+            self.render_element(
+                index,
+                element,
+                sample_rate,
+                ranges[index],
+                |sample, level| {
+                    let t = (sample - first_sample) as f32 / sample_rate;
+                    let (left_gain, right_gain) = element.pan_gains(t);
+                    left[sample] = self.mix_mode.combine(left[sample], level * left_gain);
+                    right[sample] = self.mix_mode.combine(right[sample], level * right_gain);
+                },
+            );
+        }
+        self.average_voices(left, &ranges, 0);
+        self.average_voices(right, &ranges, 0);
+    }
+    /// In `MixMode::Average`, divides each sample of `wave` (which starts at sample `offset`)
+    /// by how many elements sound there.
+    fn average_voices(&self, wave: &mut [f32], ranges: &[(usize, usize)], offset: usize) {
+        if !matches!(self.mix_mode, MixMode::Average) {
+            return;
+        }
+        let mut changes = vec![0i32; wave.len() + 1];
+        for (element, &(first_sample, last_sample)) in self.elements.iter().zip(ranges) {
+            if let WaveFunction::Rest = element.function {
+                continue;
+            }
+            let first = first_sample.saturating_sub(offset).min(wave.len());
+            let last = last_sample.saturating_sub(offset).min(wave.len());
+            if first < last {
+                changes[first] += 1;
+                changes[last] -= 1;
+            }
+        }
+        let mut sounding = 0;
+        for (sample, change) in wave.iter_mut().zip(changes) {
+            sounding += change;
+            if sounding > 1 {
+                *sample /= sounding as f32;
+            }
+        }
+    }
+    /// Includes envelope release tails, so it is the length a render needs.
+    pub fn total_duration(&self) -> f32 {
+        let mut global_time = 0f32;
+        for element in self.elements.iter() {
+            let end_time = element.end_time();
+            if end_time > global_time {
+                global_time = end_time;
+            }
+        }
+        global_time
+    }
+    /// `silence_time` only pads the end; negative values count as zero and the render
+    /// methods reject non-finite ones.
+    pub fn sample_count(&self, sample_rate: u32, silence_time: f32) -> usize {
+        let global_time = self.total_duration() + silence_time.max(0.0);
+        let sample_rate_f32 = sample_rate as f32;
+        let samples_count_f32 = global_time * sample_rate_f32;
+        samples_count_f32.ceil() as usize
+    }
+    fn check_render(&self, sample_rate: u32, silence_time: f32) -> Result<(), RenderError> {
+        error::check_sample_rate(sample_rate)?;
+        if let Some(index) = self
+            .elements
+            .iter()
+            .position(|element| !(element.time >= 0.0 && element.time.is_finite()))
+        {
+            return Err(RenderError::InvalidTime {
+                index,
+                time: self.elements[index].time,
+            });
+        }
+        if let Some(index) = self
+            .elements
+            .iter()
+            .position(|element| !element.duration.is_finite())
+        {
+            return Err(RenderError::InvalidDuration {
+                index,
+                duration: self.elements[index].duration,
+            });
+        }
+        if !silence_time.is_finite() {
+            return Err(RenderError::InvalidSilenceTime(silence_time));
+        }
+        error::check_render_length(self.total_duration() + silence_time.max(0.0))
+    }
+    fn create_wave(&self, sample_rate: u32, silence_time: f32) -> Result<Vec<f32>, RenderError> {
+        self.check_render(sample_rate, silence_time)?;
+        Ok(vec![0.0; self.sample_count(sample_rate, silence_time)])
+    }
+    pub fn render_to_buffer(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+    ) -> Result<Vec<f32>, RenderError> {
+        let mut wave = Vec::new();
+        self.render_into(sample_rate, silence_time, &mut wave)?;
+        Ok(wave)
+    }
+    pub fn render_to_buffer_with_progress(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+        progress: impl FnMut(f32),
+    ) -> Result<Vec<f32>, RenderError> {
+        let mut wave = Vec::new();
+        self.render_into_with_progress(sample_rate, silence_time, &mut wave, progress)?;
+        Ok(wave)
+    }
+    /// Clears and resizes `buffer` before rendering, so its allocation can be reused.
+    pub fn render_into(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+        buffer: &mut Vec<f32>,
+    ) -> Result<(), RenderError> {
+        self.render_into_with_progress(sample_rate, silence_time, buffer, |_| {})
+    }
+    fn render_into_with_progress(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+        buffer: &mut Vec<f32>,
+        progress: impl FnMut(f32),
+    ) -> Result<(), RenderError> {
+        self.check_render(sample_rate, silence_time)?;
+        buffer.clear();
+        buffer.resize(self.sample_count(sample_rate, silence_time), 0.0);
+        self.render_elements(sample_rate as f32, buffer, progress);
+        if let Some(target_peak) = self.normalize_peak {
+            effects::normalize(buffer, target_peak);
+        }
+        Ok(())
+    }
+    /// Elements without a bus are summed into `MAIN_BUS`; peak normalization is not applied.
+    pub fn render_buses(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+    ) -> Result<HashMap<String, Vec<f32>>, RenderError> {
+        let silence = self.create_wave(sample_rate, silence_time)?;
+        let sample_rate = sample_rate as f32;
+        let ranges = self.element_ranges(sample_rate, silence.len());
+        let mut buses: HashMap<String, Vec<f32>> = HashMap::new();
+        for (index, element) in self.elements.iter().enumerate() {
+            let name = element.bus.as_deref().unwrap_or(MAIN_BUS);
+            let wave = buses
+                .entry(name.to_string())
+                .or_insert_with(|| silence.clone());
+            self.render_element(
+                index,
+                element,
+                sample_rate,
+                ranges[index],
+                |sample, level| {
+                    wave[sample] = self.mix_mode.combine(wave[sample], level);
+                },
+            );
+        }
+        for (name, wave) in buses.iter_mut() {
+            let bus_ranges: Vec<(usize, usize)> = self
+                .elements
+                .iter()
+                .zip(ranges.iter())
+                .map(|(element, &range)| {
+                    if element.bus.as_deref().unwrap_or(MAIN_BUS) == name {
+                        range
+                    } else {
+                        (0, 0)
+                    }
+                })
+                .collect();
+            self.average_voices(wave, &bus_ranges, 0);
+        }
+        Ok(buses)
+    }
+    /// Notes that start before `start` keep their phase; peak normalization is not applied.
+    /// Non-finite or reversed windows and windows longer than `MAX_RENDER_SECONDS` are errors.
+    pub fn render_range(
+        &self,
+        sample_rate: u32,
+        start: f32,
+        end: f32,
+    ) -> Result<Vec<f32>, RenderError> {
+        self.check_render(sample_rate, 0.0)?;
+        if !start.is_finite() || !end.is_finite() || end < start {
+            return Err(RenderError::InvalidRange { start, end });
+        }
+        error::check_render_length(end - start.max(0.0))?;
+        let sample_rate = sample_rate as f32;
+        let first_sample = (start.max(0.0) * sample_rate) as usize;
+        let last_sample = ((end * sample_rate).ceil() as usize).max(first_sample);
+        let mut wave = vec![0.0; last_sample - first_sample];
+        let ranges = self.element_ranges(sample_rate, last_sample);
+        for (index, element) in self.elements.iter().enumerate() {
+            if element.time >= end || element.end_time() <= start {
+                continue;
+            }
+            self.render_element(
+                index,
+                element,
+                sample_rate,
+                ranges[index],
+                |sample, level| {
+                    if sample >= first_sample {
+                        let mixed = &mut wave[sample - first_sample];
+                        *mixed = self.mix_mode.combine(*mixed, level);
+                    }
+                },
+            );
+        }
+        self.average_voices(&mut wave, &ranges, first_sample);
+        Ok(wave)
+    }
+    pub fn samples(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+    ) -> Result<impl Iterator<Item = f32> + '_, RenderError> {
+        self.check_render(sample_rate, silence_time)?;
+        let length = self.sample_count(sample_rate, silence_time);
+        let mut gain = None;
+        if let Some(target_peak) = self.normalize_peak {
+            let peak = Samples::new(self, sample_rate, length, None)
+                .fold(0f32, |peak, sample| peak.max(sample.abs()));
+            if peak != 0.0 {
+                gain = Some(target_peak / peak);
+            }
+        }
+        Ok(Samples::new(self, sample_rate, length, gain))
+    }
+    #[cfg(feature = "parallel")]
+    pub fn render_to_buffer_parallel(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+    ) -> Result<Vec<f32>, RenderError> {
+        let mut wave = self.create_wave(sample_rate, silence_time)?;
+        self.render_elements_parallel(sample_rate as f32, &mut wave);
+        if let Some(target_peak) = self.normalize_peak {
+            effects::normalize(&mut wave, target_peak);
+        }
+        Ok(wave)
+    }
+    pub fn render_stereo_to_buffer(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+    ) -> Result<Vec<f32>, RenderError> {
+        let mut left = self.create_wave(sample_rate, silence_time)?;
+        let mut right = left.clone();
+        self.render_elements_stereo(sample_rate as f32, &mut left, &mut right);
+        let mut wave = interleave(&left, &right);
+        if let Some(target_peak) = self.normalize_peak {
+            effects::normalize(&mut wave, target_peak);
+        }
+        Ok(wave)
+    }
+    #[cfg(feature = "fs")]
+    fn render_chunks<E>(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+        chunk_samples: usize,
+        mut consume: impl FnMut(&[f32]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let length = self.sample_count(sample_rate, silence_time);
+        let chunk_samples = chunk_samples.max(1);
+        let sample_rate = sample_rate as f32;
+        let ranges = self.element_ranges(sample_rate, length);
+        let mut voices: Vec<Option<Voice>> = self.elements.iter().map(|_| None).collect();
+        let mut left = Vec::with_capacity(chunk_samples);
+        let mut right = Vec::with_capacity(chunk_samples);
+        let mut start = 0;
+        while start < length {
+            let end = (start + chunk_samples).min(length);
+            left.clear();
+            left.resize(end - start, 0.0);
+            right.clear();
+            right.resize(end - start, 0.0);
+            for (index, element) in self.elements.iter().enumerate() {
+                if let WaveFunction::Rest = element.function {
+                    continue;
+                }
+                let (first_sample, last_sample) = ranges[index];
+                if first_sample >= end || last_sample <= start {
+                    continue;
+                }
+                let voice = voices[index].get_or_insert_with(|| {
+                    Voice::new(self, index, element, sample_rate, ranges[index])
+                });
+                for sample in start.max(first_sample)..end.min(last_sample) {
+                    let level = voice.next_level();
+                    if self.stereo {
+                        let t = (sample - first_sample) as f32 / sample_rate;
+                        let (left_gain, right_gain) = element.pan_gains(t);
+                        let (left, right) = (&mut left[sample - start], &mut right[sample - start]);
+                        *left = self.mix_mode.combine(*left, level * left_gain);
+                        *right = self.mix_mode.combine(*right, level * right_gain);
+                    } else {
+                        let mixed = &mut left[sample - start];
+                        *mixed = self.mix_mode.combine(*mixed, level);
+                    }
+                }
+                if last_sample <= end {
+                    voices[index] = None;
+                }
+            }
+            self.average_voices(&mut left, &ranges, start);
+            self.average_voices(&mut right, &ranges, start);
+            if self.stereo {
+                consume(&interleave(&left, &right))?;
+            } else if self.upmix {
+                consume(&mono_to_stereo(&left))?;
+            } else {
+                consume(&left)?;
+            }
+            start = end;
+        }
+        Ok(())
+    }
+    #[cfg(feature = "fs")]
+    pub fn render_streaming(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+        file_name: &str,
+        bit_depth: BitDepth,
+        chunk_samples: usize,
+    ) -> Result<(), RenderError> {
+        self.check_render(sample_rate, silence_time)?;
+        let mut gain = None;
+        if let Some(target_peak) = self.normalize_peak {
+            let mut peak = 0f32;
+            self.render_chunks(sample_rate, silence_time, chunk_samples, |chunk| {
+                peak = chunk
+                    .iter()
+                    .fold(peak, |peak, sample| peak.max(sample.abs()));
+                Ok::<(), hound::Error>(())
+            })?;
+            if peak != 0.0 {
+                gain = Some(target_peak / peak);
+            }
+        }
+        let wav_spec = bit_depth.wav_spec(sample_rate, self.channels());
+        let mut writer = hound::WavWriter::create(file_name, wav_spec)?;
+        let mut dither = self.ditherer();
+        self.render_chunks(sample_rate, silence_time, chunk_samples, |chunk| {
+            for &sample in chunk {
+                let sample = match gain {
+                    Some(gain) => sample * gain,
+                    None => sample,
+                };
+                write_sample(&mut writer, sample, &bit_depth, dither.as_mut())?;
+            }
+            Ok::<(), hound::Error>(())
+        })?;
+        writer.finalize()?;
+        Ok(())
+    }
+    pub fn render_to_pcm(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+        format: PcmFormat,
+    ) -> Result<Vec<u8>, RenderError> {
+        let wave = if self.stereo {
+            self.render_stereo_to_buffer(sample_rate, silence_time)?
+        } else if self.upmix {
+            mono_to_stereo(&self.render_to_buffer(sample_rate, silence_time)?)
+        } else {
+            self.render_to_buffer(sample_rate, silence_time)?
+        };
+        let mut bytes = Vec::with_capacity(wave.len() * format.bytes_per_sample());
+        let mut dither = self.ditherer();
+        for sample in wave {
+            match format {
+                PcmFormat::Int16 => {
+                    let sample = quantize(sample, 16, dither.as_mut()) as i16;
+                    bytes.extend_from_slice(&sample.to_le_bytes());
+                }
+                PcmFormat::Float32 => bytes.extend_from_slice(&sample.to_le_bytes()),
+            }
+        }
+        Ok(bytes)
+    }
+    #[cfg(feature = "fs")]
+    pub fn from_midi(
+        path: &str,
+        function: WaveFunction,
+        ticks_per_beat: u16,
+        tempo_bpm: f32,
+    ) -> Result<Generator, midi::MidiError> {
+        midi::read_midi(path, function, ticks_per_beat, tempo_bpm)
+    }
+    #[cfg(feature = "fs")]
+    pub fn to_midi(&self, path: &str, tempo_bpm: f32) -> Result<(), midi::MidiError> {
+        midi::write_midi(self, path, tempo_bpm)
+    }
+    #[cfg(feature = "playback")]
+    pub fn play(&self, sample_rate: u32, silence_time: f32) -> Result<(), playback::PlaybackError> {
+        playback::play_buffer(
+            self.render_to_buffer(sample_rate, silence_time)?,
+            sample_rate,
+        )
+    }
+    /// An empty generator with no silence still writes a valid, zero-length WAV file.
+    #[cfg(feature = "fs")]
+    pub fn render(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+        file_name: &str,
+        bit_depth: BitDepth,
+    ) -> Result<(), RenderError> {
+        let wave = if self.stereo {
+            self.render_stereo_to_buffer(sample_rate, silence_time)?
+        } else if self.upmix {
+            mono_to_stereo(&self.render_to_buffer(sample_rate, silence_time)?)
+        } else {
+            self.render_to_buffer(sample_rate, silence_time)?
+        };
+        let dither = self.ditherer();
+        write_wav(
+            file_name,
+            &wave,
+            sample_rate,
+            self.channels(),
+            bit_depth,
+            dither,
+        )?;
+        Ok(())
+    }
+    /// Rests count towards `element_count` but are left out of `notes`.
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            total_duration: self.total_duration(),
+            element_count: self.elements.len(),
+            tempo_bpm: self.tempo.map(|tempo| tempo.bpm),
+            notes: self
+                .elements
+                .iter()
+                .filter(|element| !matches!(element.function, WaveFunction::Rest))
+                .map(|element| NoteEvent {
+                    time: element.time,
+                    duration: element.duration,
+                    pitch: element.pitch,
+                    amplitude: element.amplitude,
+                })
+                .collect(),
+        }
+    }
+    #[cfg(feature = "fs")]
+    pub fn render_with_metadata(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+        wav_path: &str,
+        meta_path: &str,
+        bit_depth: BitDepth,
+    ) -> Result<(), RenderError> {
+        self.render(sample_rate, silence_time, wav_path, bit_depth)?;
+        let json = serde_json::to_string_pretty(&self.metadata()).map_err(std::io::Error::from)?;
+        std::fs::write(meta_path, json)?;
+        Ok(())
+    }
+    /// Channels in the interleaved output of `render`, `render_streaming` and `render_to_pcm`.
+    pub fn channels(&self) -> u16 {
+        if self.stereo || self.upmix {
+            2
+        } else {
+            1
+        }
+    }
+    fn ditherer(&self) -> Option<Dither> {
+        match self.dither {
+            DitherMode::None => None,
+            mode => Some(Dither::new(mode, self.seed.wrapping_sub(1))),
+        }
+    }
+}
+
+/// Renders each `(generator, settings, file name)` job, in parallel with the `parallel`
+/// feature; a failed job does not stop the others.
+#[cfg(feature = "fs")]
+pub fn render_batch(jobs: &[(Generator, RenderSettings, String)]) -> Vec<Result<(), RenderError>> {
+    let render_job = |(generator, settings, file_name): &(Generator, RenderSettings, String)| {
+        generator.render(
+            settings.sample_rate,
+            settings.silence_time,
+            file_name,
+            settings.bit_depth,
+        )
+    };
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        jobs.par_iter().map(render_job).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        jobs.iter().map(render_job).collect()
+    }
+}
+
+#[cfg(feature = "fs")]
+pub fn write_wav(
+    file_name: &str,
+    wave: &[f32],
+    sample_rate: u32,
+    channels: u16,
+    bit_depth: BitDepth,
+    mut dither: Option<Dither>,
+) -> Result<(), hound::Error> {
+    let wav_spec = bit_depth.wav_spec(sample_rate, channels);
+    let mut writer = hound::WavWriter::create(file_name, wav_spec)?;
+    for &sample in wave {
+        write_sample(&mut writer, sample, &bit_depth, dither.as_mut())?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+#[cfg(feature = "fs")]
+fn write_sample<W: std::io::Write + std::io::Seek>(
+    writer: &mut hound::WavWriter<W>,
+    sample: f32,
+    bit_depth: &BitDepth,
+    dither: Option<&mut Dither>,
+) -> Result<(), hound::Error> {
+    match bit_depth {
+        BitDepth::Int16 => writer.write_sample(quantize(sample, 16, dither) as i16),
+        BitDepth::Int24 => writer.write_sample(quantize(sample, 24, dither)),
+        BitDepth::Float32 => writer.write_sample(sample),
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum DitherMode {
+    #[default]
+    None,
+    /// Uniform noise of +/-0.5 LSB.
+    Rectangular,
+    /// Triangular noise of +/-1 LSB.
+    Triangular,
+    /// Triangular noise plus first-order error feedback, which tilts the quantization noise
+    /// towards high frequencies where it is less audible.
+    NoiseShaped,
+}
+
+pub struct Dither {
+    mode: DitherMode,
+    rng: Rng,
+    error: f32,
+}
+
+impl Dither {
+    pub fn new(mode: DitherMode, seed: u64) -> Dither {
+        Dither {
+            mode,
+            rng: Rng::new(seed),
+            error: 0.0,
+        }
+    }
+}
+
+fn quantize(sample: f32, bits: u32, dither: Option<&mut Dither>) -> i32 {
+    let full_scale = ((1i32 << (bits - 1)) - 1) as f32;
+    let sample = sample.clamp(-1.0, 1.0) * full_scale;
+    let dither = match dither {
+        Some(dither) => dither,
+        None => return sample.round() as i32,
+    };
+    let rng = &mut dither.rng;
+    let shaped = match dither.mode {
+        DitherMode::NoiseShaped => sample - dither.error,
+        _ => sample,
+    };
+    let noise = match dither.mode {
+        DitherMode::None => 0.0,
+        DitherMode::Rectangular => rng.next_f32() - 0.5,
+        DitherMode::Triangular | DitherMode::NoiseShaped => rng.next_f32() - rng.next_f32(),
+    };
+    let quantized = (shaped + noise).round().clamp(-full_scale, full_scale) as i32;
+    dither.error = quantized as f32 - shaped;
+    quantized
+}
+
+pub fn interleave(left: &[f32], right: &[f32]) -> Vec<f32> {
+    let mut wave = Vec::with_capacity(left.len() * 2);
+    for (left, right) in left.iter().zip(right.iter()) {
+        wave.push(*left);
+        wave.push(*right);
+    }
+    wave
+}
+
+pub fn mono_to_stereo(mono: &[f32]) -> Vec<f32> {
+    interleave(mono, mono)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(feature = "fs"))]
+    fn renders_into_buffer_without_fs() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(wave.len(), 4000);
+        assert!(wave.iter().any(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn upmix_duplicates_mono_on_every_output_path() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.1, 0.0, 1.0);
+        generator.upmix = true;
+        let mono = generator.render_to_buffer(8000, 0.0).unwrap();
+        let pcm = generator
+            .render_to_pcm(8000, 0.0, PcmFormat::Float32)
+            .unwrap();
+        let pcm: Vec<f32> = pcm
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert_eq!(pcm, mono_to_stereo(&mono));
+        #[cfg(feature = "fs")]
+        for streaming in [false, true] {
+            let path = std::env::temp_dir().join(format!("music-test-upmix-{}.wav", streaming));
+            let path = path.to_str().unwrap();
+            if streaming {
+                generator
+                    .render_streaming(8000, 0.0, path, BitDepth::Float32, 64)
+                    .unwrap();
+            } else {
+                generator
+                    .render(8000, 0.0, path, BitDepth::Float32)
+                    .unwrap();
+            }
+            let mut reader = hound::WavReader::open(path).unwrap();
+            assert_eq!(reader.spec().channels, 2);
+            let samples: Vec<f32> = reader.samples().map(Result::unwrap).collect();
+            assert_eq!(samples, mono_to_stereo(&mono));
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn negative_silence_time_is_clamped() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 1.0, 0.0, 1.0);
+        assert_eq!(generator.sample_count(8000, -5.0), 8000);
+        assert_eq!(generator.render_to_buffer(8000, -0.01).unwrap().len(), 8000);
+    }
+
+    #[test]
+    fn unbounded_lengths_are_rejected() {
+        let mut generator = Generator::new();
+        assert!(matches!(
+            generator.render_to_buffer(48000, f32::INFINITY),
+            Err(RenderError::InvalidSilenceTime(_))
+        ));
+        assert!(matches!(
+            generator.render_to_buffer(48000, f32::NAN),
+            Err(RenderError::InvalidSilenceTime(_))
+        ));
+        generator.add_music_element(WaveFunction::Sine, 0.0, f32::INFINITY, 0.0, 1.0);
+        assert!(matches!(
+            generator.render_to_buffer(48000, 0.0),
+            Err(RenderError::InvalidDuration { index: 0, .. })
+        ));
+        generator.elements[0].duration = 1e30;
+        assert!(matches!(
+            generator.render_to_buffer(48000, 0.0),
+            Err(RenderError::TooLong(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn renders_wav_file_with_fs() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+        let path = std::env::temp_dir().join("music-test-fs.wav");
+        let path = path.to_str().unwrap();
+        generator.render(8000, 0.0, path, BitDepth::Int16).unwrap();
+        let reader = hound::WavReader::open(path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 8000);
+        assert_eq!(reader.len(), 4000);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sine_at_reference_peaks_near_unity() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 1.0, 0.0, 1.0);
+        let wave = generator.render_to_buffer(48000, 0.0).unwrap();
+        assert_eq!(wave.len(), 48000);
+        let peak = effects::analyze(&wave).peak;
+        assert!((peak - 1.0).abs() < 1e-3, "peak {}", peak);
+    }
+
+    #[test]
+    fn half_amplitude_notes_stay_within_full_scale() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Square, 0.0, 0.5, 0.0, 0.5);
+        generator.add_music_element(WaveFunction::Square, 0.0, 0.5, 7.0, 0.5);
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        let peak = effects::analyze(&wave).peak;
+        assert!(peak <= 1.0, "peak {}", peak);
+        assert!(peak > 0.9);
+    }
+
+    #[test]
+    fn envelope_starts_and_ends_near_zero() {
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        let element = generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+        element.envelope = Some(Envelope::new(0.05, 0.1, 0.5, 0.1));
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(wave.len(), 4800);
+        assert!(wave[0].abs() < 1e-3);
+        assert!(wave[wave.len() - 1].abs() < 1e-2);
+        assert!(effects::analyze(&wave[4000..]).peak > 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn render_to_invalid_path_returns_error() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.1, 0.0, 1.0);
+        let path = std::env::temp_dir()
+            .join("music-missing-dir")
+            .join("out.wav");
+        let result = generator.render(8000, 0.0, path.to_str().unwrap(), BitDepth::Float32);
+        assert!(matches!(result, Err(RenderError::Wav(_))));
+    }
+
+    #[test]
+    fn elements_keep_insertion_order() {
+        let mut generator = Generator::new();
+        for index in 0..1000 {
+            generator.add_music_element(WaveFunction::Sine, index as f32, 1.0, 0.0, 1.0);
+        }
+        assert_eq!(generator.element_count(), 1000);
+        for (index, element) in generator.elements.iter().enumerate() {
+            assert_eq!(element.time, index as f32);
+        }
+    }
+
+    #[test]
+    fn normalize_peak_scales_loud_mix_to_target() {
+        let mut generator = Generator::new();
+        generator.add_chord(WaveFunction::Sine, 0.0, 0.5, &[0.0, 4.0, 7.0, 12.0], 1.0);
+        assert!(effects::analyze(&generator.render_to_buffer(8000, 0.0).unwrap()).peak > 1.0);
+        generator.normalize_peak = Some(0.8);
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert!((effects::analyze(&wave).peak - 0.8).abs() < 1e-5);
+        let mut silence = vec![0.0; 16];
+        effects::normalize(&mut silence, 0.8);
+        assert_eq!(silence, vec![0.0; 16]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn int16_wav_reads_back_clamped() {
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        generator.add_music_element(WaveFunction::Square, 0.0, 0.25, 0.0, 2.0);
+        let path = std::env::temp_dir().join("music-test-int16.wav");
+        let path = path.to_str().unwrap();
+        generator.render(8000, 0.0, path, BitDepth::Int16).unwrap();
+        let mut reader = hound::WavReader::open(path).unwrap();
+        assert_eq!(reader.spec().bits_per_sample, 16);
+        assert_eq!(reader.spec().sample_format, hound::SampleFormat::Int);
+        assert_eq!(reader.len(), 2000);
+        let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+        assert!(samples.contains(&i16::MAX));
+        assert!(samples.iter().all(|&sample| sample >= -i16::MAX));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn hard_left_pan_silences_right_channel() {
+        let mut generator = Generator::new();
+        generator
+            .add_music_element(WaveFunction::Sine, 0.0, 0.1, 0.0, 1.0)
+            .pan = -1.0;
+        let wave = generator.render_stereo_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(wave.len(), 1600);
+        assert!(wave
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .all(|sample| sample.abs() < 1e-6));
+        assert!(effects::analyze(&wave).peak > 0.9);
+        generator.elements[0].pan = 0.0;
+        let wave = generator.render_stereo_to_buffer(8000, 0.0).unwrap();
+        assert!(wave.chunks(2).all(|frame| frame[0] == frame[1]));
+    }
+
+    #[test]
+    fn custom_constant_closure_renders_constant_buffer() {
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        let function = WaveFunction::Custom(Arc::new(|_, _| 0.25));
+        generator.add_music_element(function, 0.0, 0.1, 0.0, 1.0);
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(wave.len(), 800);
+        assert!(wave.iter().all(|&sample| sample == 0.25));
+    }
+
+    /// Frequency of a steady periodic wave from its rising zero crossings.
+    fn estimate_frequency(wave: &[f32], sample_rate: f32) -> f32 {
+        let crossings: Vec<f32> = wave
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] < 0.0 && pair[1] > 0.0)
+            .map(|(index, pair)| index as f32 + pair[0] / (pair[0] - pair[1]))
+            .collect();
+        let periods = (crossings.len() - 1) as f32;
+        periods * sample_rate / (crossings[crossings.len() - 1] - crossings[0])
+    }
+
+    #[test]
+    fn tuning_reference_scales_frequencies() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 0.5, 0.5, 7.0, 1.0);
+        let standard = generator.render_to_buffer(48000, 0.0).unwrap();
+        generator.tuning_reference = 442.0;
+        let shifted = generator.render_to_buffer(48000, 0.0).unwrap();
+        for range in [0..24000, 24000..48000] {
+            let ratio = estimate_frequency(&shifted[range.clone()], 48000.0)
+                / estimate_frequency(&standard[range], 48000.0);
+            assert!((ratio - 442.0 / 440.0).abs() < 1e-4, "ratio {}", ratio);
+        }
+        assert!((estimate_frequency(&shifted[..24000], 48000.0) - 442.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn buffer_length_covers_last_note_and_silence() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Square, 0.25, 0.5, 3.0, 1.0);
+        let wave = generator.render_to_buffer(8000, 0.25).unwrap();
+        assert_eq!(wave.len(), ((0.75 + 0.25) * 8000.0) as usize);
+    }
+
+    #[test]
+    fn note_ending_at_total_duration_fits_buffer() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.1, 0.2, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 0.3, 0.7, 0.0, 1.0);
+        let element = generator.add_music_element(WaveFunction::Sine, 0.7, 0.3, 0.0, 1.0);
+        element.envelope = Some(Envelope::new(0.01, 0.0, 1.0, 0.0));
+        for sample_rate in [8000, 44100, 48000, 96000] {
+            let wave = generator.render_to_buffer(sample_rate, 0.0).unwrap();
+            let expected = (1.0 * sample_rate as f32).ceil() as usize;
+            assert_eq!(wave.len(), expected);
+            assert_eq!(wave.len(), generator.sample_count(sample_rate, 0.0));
+        }
+    }
+
+    #[test]
+    fn glissando_over_an_octave_doubles_frequency() {
+        let mut generator = Generator::new();
+        generator
+            .add_music_element(WaveFunction::Sine, 0.0, 2.0, 0.0, 1.0)
+            .end_note = Some(12.0);
+        let wave = generator.render_to_buffer(48000, 0.0).unwrap();
+        let start = estimate_frequency(&wave[..2400], 48000.0);
+        let end = estimate_frequency(&wave[93600..], 48000.0);
+        assert!((start - 440.0).abs() < 10.0, "start {}", start);
+        assert!((end - 880.0).abs() < 20.0, "end {}", end);
+        let largest_step = wave
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max);
+        assert!(largest_step < 2.0 * std::f32::consts::PI * 880.0 / 48000.0 + 1e-3);
+    }
+
+    #[test]
+    fn offset_note_onset_has_no_jump() {
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        generator.add_music_element(WaveFunction::Sine, 0.1234, 0.1, 5.0, 1.0);
+        let wave = generator.render_to_buffer(48000, 0.0).unwrap();
+        let onset = (0.1234f32 * 48000.0) as usize;
+        assert_eq!(wave[onset], 0.0);
+        let frequency = pitch::get_frequency_from_note(5.0, 440.0);
+        let largest_step = wave[onset - 10..onset + 100]
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max);
+        assert!(largest_step <= 2.0 * std::f32::consts::PI * frequency / 48000.0 + 1e-4);
+    }
+
+    #[test]
+    fn vibrato_spreads_spectrum_around_carrier() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 1.0, 0.0, 1.0);
+        let plain = generator.render_to_buffer(48000, 0.0).unwrap();
+        generator.elements[0].vibrato_rate = 6.0;
+        assert_eq!(generator.render_to_buffer(48000, 0.0).unwrap(), plain);
+        generator.elements[0].vibrato_depth = 1.0;
+        let vibrato = generator.render_to_buffer(48000, 0.0).unwrap();
+        let off_carrier = |wave: &[f32]| -> f32 {
+            effects::spectrum(wave, 0, 32768, 48000.0)
+                .into_iter()
+                .filter(|(frequency, _)| (frequency - 440.0).abs() > 10.0)
+                .map(|(_, magnitude)| magnitude * magnitude)
+                .sum()
+        };
+        assert!(off_carrier(&vibrato) > 10.0 * off_carrier(&plain));
+    }
+
+    #[test]
+    fn tremolo_gain_oscillates_at_rate() {
+        let element = MusicElementBuilder::new()
+            .duration(1.0)
+            .tremolo(4.0, 0.5)
+            .build();
+        assert_eq!(element.gain_at(0.0), 1.0);
+        assert!((element.gain_at(0.125) - 0.5).abs() < 1e-5);
+        assert!((element.gain_at(0.25) - 1.0).abs() < 1e-5);
+        let mut generator = Generator::new();
+        generator.add(element);
+        let wave = generator.render_to_buffer(48000, 0.0).unwrap();
+        let envelope: Vec<f32> = wave
+            .chunks(480)
+            .map(|chunk| effects::analyze(chunk).peak)
+            .collect();
+        let troughs = envelope
+            .windows(3)
+            .filter(|window| window[1] < window[0] && window[1] < window[2])
+            .count();
+        assert_eq!(troughs, 4);
+    }
+
+    #[test]
+    fn transposing_an_octave_doubles_frequencies() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, -5.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 0.5, 0.5, 2.0, 1.0);
+        let original = generator.render_to_buffer(48000, 0.0).unwrap();
+        generator.transpose(12.0);
+        let transposed = generator.render_to_buffer(48000, 0.0).unwrap();
+        for range in [0..24000, 24000..48000] {
+            let ratio = estimate_frequency(&transposed[range.clone()], 48000.0)
+                / estimate_frequency(&original[range], 48000.0);
+            assert!((ratio - 2.0).abs() < 1e-3, "ratio {}", ratio);
+        }
+    }
+
+    #[test]
+    fn shift_and_repeat_place_copies() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 0.5, 1.0, 2.0, 1.0);
+        generator.shift_time(0.25);
+        let times: Vec<f32> = generator
+            .elements
+            .iter()
+            .map(|element| element.time)
+            .collect();
+        assert_eq!(times, vec![0.25, 0.75]);
+        let repeated = generator.repeat(3, 0.25);
+        assert_eq!(repeated.element_count(), 6);
+        let times: Vec<f32> = repeated
+            .elements
+            .iter()
+            .map(|element| element.time)
+            .collect();
+        assert_eq!(times, vec![0.25, 0.75, 2.25, 2.75, 4.25, 4.75]);
+        generator.elements[1].envelope = Some(Envelope::new(0.0, 0.0, 1.0, 0.5));
+        let repeated = generator.repeat(2, 0.25);
+        assert_eq!(repeated.elements[2].time, 2.75);
+        assert!(repeated.elements[1].end_time() < repeated.elements[2].time);
+    }
+
+    #[test]
+    fn merged_render_is_sum_of_parts() {
+        let mut melody = Generator::new();
+        melody.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 0.5);
+        let mut bass = Generator::new();
+        bass.add_music_element(WaveFunction::Triangle, 0.25, 0.5, -24.0, 0.5);
+        let mut expected = bass.render_to_buffer(8000, 0.0).unwrap();
+        for (sample, melody) in expected
+            .iter_mut()
+            .zip(melody.render_to_buffer(8000, 0.0).unwrap())
+        {
+            *sample += melody;
+        }
+        melody.merge(bass);
+        assert_eq!(melody.element_count(), 2);
+        assert_eq!(melody.elements[1].time, 0.25);
+        let merged = melody.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(merged.len(), expected.len());
+        for (merged, expected) in merged.iter().zip(expected.iter()) {
+            assert!((merged - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn add_chord_adds_simultaneous_notes() {
+        let mut generator = Generator::new();
+        generator.add_chord(WaveFunction::Triangle, 1.5, 0.5, &[0.0, 4.0, 7.0], 0.3);
+        assert_eq!(generator.element_count(), 3);
+        for (element, note) in generator.elements.iter().zip([0.0, 4.0, 7.0]) {
+            assert_eq!((element.time, element.duration), (1.5, 0.5));
+            assert!(matches!(element.pitch, Pitch::Note(pitch) if pitch == note));
+            assert!(matches!(element.function, WaveFunction::Triangle));
+        }
+    }
+
+    #[test]
+    fn trailing_rest_lengthens_buffer() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+        let without_rest = generator.render_to_buffer(8000, 0.0).unwrap();
+        generator.add_rest(0.5, 0.25);
+        let with_rest = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(with_rest.len(), 6000);
+        assert_eq!(with_rest[..4000], without_rest[..]);
+        assert!(with_rest[4000..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn loudness_compensation_scales_square_against_sine() {
+        let rms_of = |function: WaveFunction, compensate: bool| {
+            let mut generator = Generator::new();
+            generator.loudness_compensation = compensate;
+            generator.add_music_element(function, 0.0, 0.5, 0.0, 1.0);
+            effects::rms(&generator.render_to_buffer(48000, 0.0).unwrap())
+        };
+        let square = rms_of(WaveFunction::Square, true) / rms_of(WaveFunction::Square, false);
+        assert!((square - WaveFunction::Square.reference_gain()).abs() < 1e-4);
+        assert_eq!(
+            rms_of(WaveFunction::Sine, true),
+            rms_of(WaveFunction::Sine, false)
+        );
+        assert!(rms_of(WaveFunction::Square, true) < rms_of(WaveFunction::Sine, true));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn wav_sample_plays_at_element_time() {
+        let data: Vec<f32> = (0..400).map(|index| (index as f32 / 400.0) - 0.5).collect();
+        let path = std::env::temp_dir().join("music-test-sample-source.wav");
+        let path = path.to_str().unwrap();
+        write_wav(path, &data, 8000, 1, BitDepth::Float32, None).unwrap();
+        let function = WaveFunction::from_wav(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        generator.add_music_element(function, 0.25, 0.05, 0.0, 1.0);
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert!(wave[..2000].iter().all(|&sample| sample == 0.0));
+        for (rendered, source) in wave[2000..].iter().zip(data.iter()) {
+            assert!((rendered - source).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn looped_sample_fills_duration() {
+        let data: Vec<f32> = (0..800).map(|index| sine(index as f32 / 80.0)).collect();
+        let function = WaveFunction::Sample {
+            data: Arc::new(data),
+            sample_rate: 8000,
+            interpolation: Interpolation::Linear,
+        };
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        generator.add_music_element(function, 0.0, 1.0, 0.0, 1.0);
+        let once = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert!(effects::analyze(&once[800..]).peak < 1e-6);
+        generator.elements[0].loop_sample = true;
+        let looped = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(looped.len(), 8000);
+        for window in looped.chunks(400) {
+            assert!(effects::analyze(window).peak > 0.9);
+        }
+    }
+
+    #[test]
+    fn active_at_returns_sounding_elements() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 1.0, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 0.5, 1.0, 4.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 2.0, 1.0, 7.0, 1.0);
+        let starts = |time| -> Vec<f32> {
+            generator
+                .active_at(time)
+                .iter()
+                .map(|element| element.time)
+                .collect()
+        };
+        assert_eq!(starts(0.25), vec![0.0]);
+        assert_eq!(starts(0.75), vec![0.0, 0.5]);
+        assert_eq!(starts(1.0), vec![0.5]);
+        assert!(starts(1.75).is_empty());
+        assert_eq!(starts(2.0), vec![2.0]);
+    }
+
+    #[test]
+    fn json_round_trip_reproduces_elements() {
+        let mut generator = Generator::with_seed(7);
+        generator.tempo = Some(Tempo::new(90.0));
+        generator
+            .add_music_element(WaveFunction::Square, 0.0, 0.5, -3.0, 0.8)
+            .pan = 0.5;
+        generator.add(
+            MusicElementBuilder::new()
+                .function(WaveFunction::Fm {
+                    ratio: 2.0,
+                    index: 1.5,
+                })
+                .time(0.5)
+                .hz(330.0)
+                .envelope(Envelope::new(0.01, 0.1, 0.7, 0.2))
+                .build(),
+        );
+        generator.add_rest(1.0, 0.5);
+        let json = generator.to_json().unwrap();
+        let restored = Generator::from_json(&json).unwrap();
+        assert_eq!(restored.to_json().unwrap(), json);
+        assert_eq!(restored.element_count(), 3);
+        assert_eq!(restored.seed, 7);
+        assert_eq!(
+            restored.render_to_buffer(8000, 0.0).unwrap(),
+            generator.render_to_buffer(8000, 0.0).unwrap()
+        );
+        generator.add_music_element(
+            WaveFunction::Custom(Arc::new(|_, _| 0.0)),
+            0.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        assert!(generator.to_json().is_err());
+    }
+
+    #[test]
+    fn hz_element_matches_note_element() {
+        let mut by_note = Generator::new();
+        by_note.add_music_element(WaveFunction::Sawtooth, 0.0, 0.25, 0.0, 1.0);
+        let mut by_hz = Generator::new();
+        by_hz.add_element_hz(WaveFunction::Sawtooth, 0.0, 0.25, 440.0, 1.0);
+        assert!(matches!(by_hz.elements[0].pitch, Pitch::Hz(frequency) if frequency == 440.0));
+        assert_eq!(
+            by_hz.render_to_buffer(8000, 0.0).unwrap(),
+            by_note.render_to_buffer(8000, 0.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn click_reduction_ramps_note_edges() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Square, 0.0, 0.1, 0.0, 1.0);
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(wave[0], 0.0);
+        assert!(wave[1].abs() < 0.1 && wave[10].abs() < 0.5);
+        assert_eq!(wave[40].abs(), 1.0);
+        assert_eq!(wave[wave.len() - 1], 0.0);
+        generator.click_reduction_ms = 0.0;
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(wave[0], 1.0);
+    }
+
+    #[test]
+    fn hundred_cents_detune_equals_a_semitone() {
+        let mut detuned = Generator::new();
+        detuned
+            .add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0)
+            .detune_cents = 100.0;
+        let mut semitone = Generator::new();
+        semitone.add_music_element(WaveFunction::Sine, 0.0, 0.5, 1.0, 1.0);
+        let tuning = Tuning::default();
+        let detuned_frequency = detuned.elements[0].frequency_at(0.0, &tuning, 440.0);
+        let semitone_frequency = semitone.elements[0].frequency_at(0.0, &tuning, 440.0);
+        assert!((detuned_frequency - semitone_frequency).abs() < 1e-3);
+        let ratio = estimate_frequency(&detuned.render_to_buffer(48000, 0.0).unwrap(), 48000.0)
+            / estimate_frequency(&semitone.render_to_buffer(48000, 0.0).unwrap(), 48000.0);
+        assert!((ratio - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn removing_middle_element_keeps_order() {
+        let mut generator = Generator::new();
+        for note in [0.0, 1.0, 2.0, 3.0] {
+            generator.add_music_element(WaveFunction::Sine, note, 1.0, note, 1.0);
+        }
+        let removed = generator.remove_element(1).unwrap();
+        assert_eq!(removed.time, 1.0);
+        assert!(generator.remove_element(3).is_none());
+        assert_eq!(generator.element_count(), 3);
+        let times: Vec<f32> = generator
+            .elements
+            .iter()
+            .map(|element| element.time)
+            .collect();
+        assert_eq!(times, vec![0.0, 2.0, 3.0]);
+        generator.clear();
+        assert_eq!(generator.element_count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn streamed_render_matches_one_shot() {
+        let mut generator = Generator::with_seed(3);
+        generator.normalize_peak = Some(0.9);
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.3, 0.0, 1.0);
+        generator
+            .add_music_element(WaveFunction::PinkNoise, 0.1, 0.2, 0.0, 0.5)
+            .pan = 0.7;
+        generator
+            .add_music_element(WaveFunction::Pluck, 0.2, 0.2, -12.0, 1.0)
+            .envelope = Some(Envelope::new(0.01, 0.05, 0.5, 0.1));
+        let path = std::env::temp_dir().join("music-test-streamed.wav");
+        let path = path.to_str().unwrap();
+        for stereo in [false, true] {
+            generator.stereo = stereo;
+            let expected = if stereo {
+                generator.render_stereo_to_buffer(8000, 0.1).unwrap()
+            } else {
+                generator.render_to_buffer(8000, 0.1).unwrap()
+            };
+            for chunk_samples in [1, 7, 1000, 100_000] {
+                generator
+                    .render_streaming(8000, 0.1, path, BitDepth::Float32, chunk_samples)
+                    .unwrap();
+                let mut reader = hound::WavReader::open(path).unwrap();
+                let streamed: Vec<f32> = reader.samples().map(Result::unwrap).collect();
+                assert_eq!(
+                    streamed, expected,
+                    "stereo {} chunk {}",
+                    stereo, chunk_samples
+                );
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn parallel_render_matches_sequential() {
+        let mut generator = Generator::with_seed(11);
+        generator.mix_mode = MixMode::Average;
+        for index in 0..200 {
+            let function = match index % 3 {
+                0 => WaveFunction::Sine,
+                1 => WaveFunction::WhiteNoise,
+                _ => WaveFunction::Pluck,
+            };
+            let time = index as f32 * 0.01;
+            generator.add_music_element(function, time, 0.2, (index % 24) as f32 - 12.0, 0.1);
+        }
+        assert_eq!(
+            generator.render_to_buffer_parallel(8000, 0.0).unwrap(),
+            generator.render_to_buffer(8000, 0.0).unwrap()
+        );
+    }
+
+    /// Largest spectrum magnitude within 5 Hz of `frequency`.
+    fn magnitude_near(wave: &[f32], frequency: f32, sample_rate: f32) -> f32 {
+        effects::spectrum(wave, 0, wave.len().min(32768), sample_rate)
+            .into_iter()
+            .filter(|(bin, _)| (bin - frequency).abs() <= 5.0)
+            .fold(0.0, |peak, (_, magnitude)| peak.max(magnitude))
+    }
+
+    #[test]
+    fn ring_modulation_moves_energy_to_sidebands() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 1.0, 0.0, 1.0);
+        let plain = generator.render_to_buffer(48000, 0.0).unwrap();
+        generator.elements[0].ring_mod_hz = Some(0.0);
+        assert_eq!(generator.render_to_buffer(48000, 0.0).unwrap(), plain);
+        generator.elements[0].ring_mod_hz = Some(100.0);
+        let ring = generator.render_to_buffer(48000, 0.0).unwrap();
+        assert!(magnitude_near(&ring, 340.0, 48000.0) > 0.4);
+        assert!(magnitude_near(&ring, 540.0, 48000.0) > 0.4);
+        assert!(magnitude_near(&ring, 440.0, 48000.0) < 0.05);
+        assert!(magnitude_near(&plain, 440.0, 48000.0) > 0.9);
+    }
+
+    #[test]
+    fn up_arpeggio_repeats_in_time() {
+        let mut generator = Generator::new();
+        generator.add_arpeggio(
+            WaveFunction::Square,
+            1.0,
+            0.25,
+            &[0.0, 4.0, 7.0],
+            ArpPattern::Up,
+            2,
+            1.0,
+        );
+        assert_eq!(generator.element_count(), 6);
+        for (step, element) in generator.elements.iter().enumerate() {
+            assert_eq!(element.time, 1.0 + 0.25 * step as f32);
+            assert_eq!(element.duration, 0.25);
+            let note = [0.0, 4.0, 7.0][step % 3];
+            assert!(matches!(element.pitch, Pitch::Note(pitch) if pitch == note));
+        }
+        assert_eq!(
+            ArpPattern::Down.order(&[0.0, 4.0, 7.0]),
+            vec![7.0, 4.0, 0.0]
+        );
+        assert_eq!(
+            ArpPattern::UpDown.order(&[0.0, 4.0, 7.0, 12.0]),
+            vec![0.0, 4.0, 7.0, 12.0, 7.0, 4.0]
+        );
+    }
+
+    #[test]
+    fn quantize_snaps_times_to_grid() {
+        let mut generator = Generator::new();
+        for time in [0.01, 0.24, 0.26, 0.6, 0.874] {
+            generator.add_music_element(WaveFunction::Sine, time, 0.1, 0.0, 1.0);
+        }
+        generator.quantize(0.0);
+        assert_eq!(generator.elements[0].time, 0.01);
+        generator.quantize(0.25);
+        let times: Vec<f32> = generator
+            .elements
+            .iter()
+            .map(|element| element.time)
+            .collect();
+        assert_eq!(times, vec![0.0, 0.25, 0.25, 0.5, 0.75]);
+    }
+
+    #[test]
+    fn high_velocity_attacks_faster_and_louder() {
+        let note = |velocity: f32| {
+            MusicElementBuilder::new()
+                .envelope(Envelope::new(0.1, 0.0, 1.0, 0.0))
+                .velocity(velocity)
+                .build()
+        };
+        let (hard, soft) = (note(1.0), note(0.3));
+        let attack_end = |element: &MusicElement| {
+            (0..1000)
+                .map(|step| step as f32 * 0.0001)
+                .find(|&t| element.gain_at(t) >= 0.99 * element.gain_at(0.5))
+                .unwrap()
+        };
+        assert!(attack_end(&hard) < attack_end(&soft));
+        assert!(hard.gain_at(0.5) > soft.gain_at(0.5));
+        assert!((hard.gain_at(0.5) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn master_gain_halves_every_sample() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Triangle, 0.0, 0.25, 0.0, 0.8);
+        generator.add_music_element(WaveFunction::Sine, 0.1, 0.25, 5.0, 0.6);
+        let full = generator.render_to_buffer(8000, 0.0).unwrap();
+        generator.master_gain = 0.5;
+        let half = generator.render_to_buffer(8000, 0.0).unwrap();
+        for (half, full) in half.iter().zip(full.iter()) {
+            assert!((half - 0.5 * full).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn pcm_length_matches_sample_count() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.3, 0.0, 1.0);
+        let count = generator.sample_count(8000, 0.1);
+        for format in [PcmFormat::Int16, PcmFormat::Float32] {
+            let bytes_per_sample = format.bytes_per_sample();
+            let bytes = generator.render_to_pcm(8000, 0.1, format).unwrap();
+            assert_eq!(bytes.len(), count * bytes_per_sample);
+        }
+        let wave = generator.render_to_buffer(8000, 0.1).unwrap();
+        let bytes = generator
+            .render_to_pcm(8000, 0.1, PcmFormat::Int16)
+            .unwrap();
+        let sample = i16::from_le_bytes([bytes[40], bytes[41]]);
+        assert_eq!(sample, (wave[20] * i16::MAX as f32).round() as i16);
+    }
+
+    #[test]
+    fn dither_keeps_quiet_fade_out_audible() {
+        let lsb = 1.0 / i16::MAX as f32;
+        let fade: Vec<f32> = (0..1000)
+            .map(|i| 2.0 * lsb * (1.0 - i as f32 / 1000.0))
+            .collect();
+        let tail = &fade[800..];
+        assert!(tail.iter().all(|&sample| quantize(sample, 16, None) == 0));
+        let mut dither = Dither::new(DitherMode::Triangular, 3);
+        let dithered: Vec<i32> = tail
+            .iter()
+            .map(|&sample| quantize(sample, 16, Some(&mut dither)))
+            .collect();
+        assert!(dithered.iter().filter(|&&sample| sample != 0).count() > 20);
+        let mut again = Dither::new(DitherMode::Triangular, 3);
+        let repeated: Vec<i32> = tail
+            .iter()
+            .map(|&sample| quantize(sample, 16, Some(&mut again)))
+            .collect();
+        assert_eq!(dithered, repeated);
+    }
+
+    #[test]
+    fn progress_callback_reaches_completion() {
+        let mut generator = Generator::new();
+        for index in 0..8 {
+            generator.add_music_element(WaveFunction::Sine, index as f32 * 0.1, 0.1, 0.0, 1.0);
+        }
+        let mut reports = Vec::new();
+        generator
+            .render_to_buffer_with_progress(8000, 0.0, |fraction| reports.push(fraction))
+            .unwrap();
+        assert!(reports.len() > 1 && reports.len() < 800);
+        assert!(reports.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!((reports.last().unwrap() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn release_continues_after_gate_ends() {
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        generator
+            .add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0)
+            .envelope = Some(Envelope::new(0.0, 0.0, 1.0, 0.5));
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert!(wave.len() >= 8000);
+        let early_release = effects::rms(&wave[4000..4800]);
+        let late_release = effects::rms(&wave[7000..7800]);
+        assert!(early_release > 0.3);
+        assert!(late_release > 0.0 && late_release < early_release);
+    }
+
+    #[test]
+    fn sample_iterator_matches_render_to_buffer() {
+        let mut generator = Generator::with_seed(5);
+        generator.add_music_element(WaveFunction::Square, 0.0, 0.5, 0.0, 0.5);
+        generator.add_music_element(WaveFunction::Sine, 0.25, 0.5, 7.0, 0.8);
+        generator
+            .add_music_element(WaveFunction::WhiteNoise, 0.4, 0.2, 0.0, 0.3)
+            .envelope = Some(Envelope::new(0.05, 0.05, 0.5, 0.1));
+        for normalize_peak in [None, Some(0.9)] {
+            generator.normalize_peak = normalize_peak;
+            let buffer = generator.render_to_buffer(8000, 0.1).unwrap();
+            let lazy: Vec<f32> = generator.samples(8000, 0.1).unwrap().collect();
+            assert_eq!(lazy.len(), buffer.len());
+            for (lazy, buffered) in lazy.iter().zip(buffer.iter()) {
+                assert!((lazy - buffered).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn builder_fills_unset_fields_with_defaults() {
+        let element = MusicElementBuilder::new()
+            .function(WaveFunction::Sawtooth)
+            .note(7.0)
+            .build();
+        assert!(matches!(element.function, WaveFunction::Sawtooth));
+        assert!(matches!(element.pitch, Pitch::Note(note) if note == 7.0));
+        assert_eq!(element.time, 0.0);
+        assert_eq!(element.duration, 1.0);
+        assert_eq!(element.amplitude, 1.0);
+        assert_eq!(element.pan, 0.0);
+        assert!(element.envelope.is_none());
+        assert_eq!(element.probability, 1.0);
+        assert!(element.reset_phase);
+        let mut generator = Generator::new();
+        generator.add(element).time = 0.5;
+        assert_eq!(generator.element_count(), 1);
+        assert_eq!(generator.elements[0].time, 0.5);
+    }
+
+    #[test]
+    fn pitch_bend_ramp_raises_output_pitch() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 2.0, 0.0, 1.0);
+        generator.pitch_bend = vec![(0.5, 0.0), (1.5, 12.0)];
+        let wave = generator.render_to_buffer(48000, 0.0).unwrap();
+        let start = estimate_frequency(&wave[..19200], 48000.0);
+        let middle = estimate_frequency(&wave[47000..49000], 48000.0);
+        let end = estimate_frequency(&wave[76800..93600], 48000.0);
+        assert!((start - 440.0).abs() < 5.0, "start {}", start);
+        assert!(
+            middle > start + 100.0 && middle < end - 100.0,
+            "middle {}",
+            middle
+        );
+        assert!((end - 880.0).abs() < 10.0, "end {}", end);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn writes_24_bit_wav() {
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        generator.add_music_element(WaveFunction::Square, 0.0, 0.5, 0.0, 1.0);
+        let path = std::env::temp_dir().join("music-test-int24.wav");
+        let path = path.to_str().unwrap();
+        generator.render(8000, 0.0, path, BitDepth::Int24).unwrap();
+        let mut reader = hound::WavReader::open(path).unwrap();
+        let spec = reader.spec();
+        assert_eq!(spec.bits_per_sample, 24);
+        assert!(matches!(spec.sample_format, hound::SampleFormat::Int));
+        assert_eq!(reader.len(), 4000);
+        let full_scale = (1 << 23) - 1;
+        assert!(reader
+            .samples::<i32>()
+            .all(|sample| sample.unwrap().abs() <= full_scale));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn pan_sweep_moves_from_left_to_right() {
+        let mut generator = Generator::new();
+        let element = generator.add_music_element(WaveFunction::Sine, 0.0, 1.0, 0.0, 1.0);
+        element.pan = -1.0;
+        element.pan_end = Some(1.0);
+        let wave = generator.render_stereo_to_buffer(8000, 0.0).unwrap();
+        let channel = |frames: &[f32], offset: usize| -> Vec<f32> {
+            frames.iter().skip(offset).step_by(2).copied().collect()
+        };
+        let start = &wave[..1600];
+        let end = &wave[wave.len() - 1600..];
+        assert!(effects::rms(&channel(start, 0)) > 4.0 * effects::rms(&channel(start, 1)));
+        assert!(effects::rms(&channel(end, 1)) > 4.0 * effects::rms(&channel(end, 0)));
+        let (middle_left, middle_right) = generator.elements[0].pan_gains(0.5);
+        assert!((middle_left - middle_right).abs() < 1e-3);
+        assert!((middle_left * middle_left + middle_right * middle_right - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn total_duration_is_latest_end_time() {
+        let mut generator = Generator::new();
+        assert_eq!(generator.total_duration(), 0.0);
+        generator.add_music_element(WaveFunction::Sine, 0.0, 3.0, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 1.0, 1.0, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 2.0, 0.5, 0.0, 1.0);
+        assert_eq!(generator.total_duration(), 3.0);
+        generator.add_music_element(WaveFunction::Sine, 2.5, 1.5, 0.0, 1.0);
+        assert_eq!(generator.total_duration(), 4.0);
+        assert_eq!(generator.sample_count(8000, 0.0), 32000);
+    }
+
+    #[test]
+    fn same_seed_renders_identical_noise() {
+        let render = |seed: u64| {
+            let mut generator = Generator::with_seed(seed);
+            generator.add_music_element(WaveFunction::WhiteNoise, 0.0, 0.25, 0.0, 1.0);
+            generator.add_music_element(WaveFunction::PinkNoise, 0.1, 0.25, 0.0, 1.0);
+            generator.dither = DitherMode::Triangular;
+            generator
+                .render_to_pcm(8000, 0.0, PcmFormat::Int16)
+                .unwrap()
+        };
+        assert_eq!(render(11), render(11));
+        assert_ne!(render(11), render(12));
+    }
+
+    #[test]
+    fn swing_delays_only_off_beats() {
+        let mut generator = Generator::new();
+        for index in 0..4 {
+            generator.add_music_element(WaveFunction::Sine, index as f32 * 0.25, 0.2, 0.0, 1.0);
+        }
+        let times = |generator: &Generator| -> Vec<f32> {
+            generator
+                .elements
+                .iter()
+                .map(|element| element.time)
+                .collect()
+        };
+        generator.apply_swing(0.25, 0.0);
+        assert_eq!(times(&generator), vec![0.0, 0.25, 0.5, 0.75]);
+        generator.apply_swing(0.25, 0.5);
+        assert_eq!(times(&generator), vec![0.0, 0.375, 0.5, 0.875]);
+    }
+
+    #[test]
+    fn render_range_truncates_overlapping_note() {
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        generator.add_music_element(WaveFunction::Square, 0.5, 1.0, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 2.0, 0.5, 0.0, 1.0);
+        let full = generator.render_to_buffer(8000, 0.0).unwrap();
+        let window = generator.render_range(8000, 1.0, 2.0).unwrap();
+        assert_eq!(window.len(), 8000);
+        assert_eq!(&window[..4000], &full[8000..12000]);
+        assert!(window[4000..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn invalid_render_ranges_are_rejected() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 1.0, 0.0, 1.0);
+        for (start, end) in [(0.0, f32::INFINITY), (f32::NAN, 1.0), (1.0, 0.5)] {
+            assert!(matches!(
+                generator.render_range(8000, start, end),
+                Err(RenderError::InvalidRange { .. })
+            ));
+        }
+        assert!(matches!(
+            generator.render_range(8000, 0.0, 1e7),
+            Err(RenderError::TooLong(_))
+        ));
+        assert!(generator.render_range(8000, 0.5, 0.5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn humanize_jitters_within_bounds() {
+        let mut generator = Generator::with_seed(9);
+        for index in 0..16 {
+            generator.add_music_element(WaveFunction::Sine, index as f32 * 0.5, 0.25, 0.0, 0.5);
+        }
+        generator.humanize(0.02, 0.1);
+        let mut moved = 0;
+        for (index, element) in generator.elements.iter().enumerate() {
+            let offset = element.time - index as f32 * 0.5;
+            assert!(element.time >= 0.0);
+            assert!(offset.abs() <= 0.02 + 1e-6, "offset {}", offset);
+            assert!((element.amplitude - 0.5).abs() <= 0.1 + 1e-6);
+            if offset.abs() > 1e-6 {
+                moved += 1;
+            }
+        }
+        assert!(moved > 8);
+    }
+
+    #[test]
+    fn set_all_waveforms_replaces_every_function() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Square, 0.0, 1.0, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sawtooth, 1.0, 1.0, 3.0, 1.0);
+        generator.add_chord(WaveFunction::Sine, 2.0, 1.0, &[0.0, 4.0, 7.0], 0.5);
+        generator.add_rest(3.0, 1.0);
+        generator.set_all_waveforms(WaveFunction::Triangle);
+        assert_eq!(generator.element_count(), 6);
+        let (notes, rest) = generator.elements.split_at(5);
+        assert!(notes
+            .iter()
+            .all(|element| matches!(element.function, WaveFunction::Triangle)));
+        assert!(matches!(rest[0].function, WaveFunction::Rest));
+    }
+
+    #[test]
+    fn voice_limit_steals_oldest_note() {
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        for (index, note) in [0.0, 4.0, 7.0].iter().enumerate() {
+            generator.add_music_element(WaveFunction::Sine, index as f32 * 0.1, 1.0, *note, 0.3);
+        }
+        generator.max_voices = Some(2);
+        let limited = generator.render_to_buffer(8000, 0.0).unwrap();
+        generator.max_voices = None;
+        let mut newest = Generator::new();
+        newest.click_reduction_ms = 0.0;
+        newest.elements = generator.elements[1..].to_vec();
+        let expected = newest.render_to_buffer(8000, 0.0).unwrap();
+        for index in 2000..8000 {
+            assert!((limited[index] - expected[index]).abs() < 1e-5);
+        }
+        let unlimited = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert!(effects::rms(&unlimited[2000..8000]) > effects::rms(&limited[2000..8000]));
+    }
+
+    #[test]
+    fn reset_phase_notes_start_identically() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sawtooth, 0.0, 0.25, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sawtooth, 0.5123, 0.25, 0.0, 1.0);
+        assert!(generator.elements.iter().all(|element| element.reset_phase));
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        let second = (0.5123 * 8000.0) as usize;
+        assert_eq!(&wave[..200], &wave[second..second + 200]);
+    }
+
+    #[test]
+    fn buses_render_into_separate_buffers() {
+        let mut generator = Generator::new();
+        generator
+            .add_music_element(WaveFunction::WhiteNoise, 0.0, 0.25, 0.0, 1.0)
+            .bus = Some("drums".to_string());
+        generator.add_music_element(WaveFunction::Sine, 0.5, 0.5, 0.0, 1.0);
+        let buses = generator.render_buses(8000, 0.0).unwrap();
+        assert_eq!(buses.len(), 2);
+        let drums = &buses["drums"];
+        let main = &buses[MAIN_BUS];
+        assert_eq!(drums.len(), 8000);
+        assert_eq!(main.len(), 8000);
+        assert!(drums[4000..].iter().all(|&sample| sample == 0.0));
+        assert!(main[..4000].iter().all(|&sample| sample == 0.0));
+        let mixed = generator.render_to_buffer(8000, 0.0).unwrap();
+        for index in 0..mixed.len() {
+            assert!((mixed[index] - drums[index] - main[index]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn cosine_attack_reaches_half_gain_at_midpoint() {
+        let mut envelope = Envelope::new(0.2, 0.0, 1.0, 0.2);
+        envelope.curve = EnvelopeCurve::Cosine;
+        assert!((envelope.gain(0.1, 1.0) - 0.5).abs() < 1e-5);
+        assert!((envelope.gain(1.1, 1.0) - 0.5).abs() < 1e-5);
+        envelope.curve = EnvelopeCurve::Exponential;
+        assert!(envelope.gain(0.1, 1.0) < 0.2);
+        assert!((envelope.gain(0.2, 1.0) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn exponential_velocity_keeps_soft_notes_quieter() {
+        for velocity in [0.1, 0.25, 0.5, 0.75] {
+            let linear = map_velocity(velocity, VelocityCurve::Linear);
+            assert!(map_velocity(velocity, VelocityCurve::Exponential) < linear);
+            assert!(map_velocity(velocity, VelocityCurve::Logarithmic) > linear);
+        }
+        for curve in [
+            VelocityCurve::Linear,
+            VelocityCurve::Exponential,
+            VelocityCurve::Logarithmic,
+        ] {
+            assert!(map_velocity(0.0, curve).abs() < 1e-6);
+            assert!((map_velocity(1.0, curve) - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn render_into_reuses_buffer_and_matches() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Square, 0.0, 0.25, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 0.1, 0.25, 5.0, 0.5);
+        let expected = generator.render_to_buffer(8000, 0.1).unwrap();
+        let mut buffer = vec![0.7; 10_000];
+        generator.render_into(8000, 0.1, &mut buffer).unwrap();
+        assert_eq!(buffer, expected);
+        let mut buffer = Vec::with_capacity(expected.len());
+        generator.render_into(8000, 0.1, &mut buffer).unwrap();
+        assert_eq!(buffer, expected);
+    }
+
+    #[test]
+    fn out_of_range_elements_do_not_panic() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, -0.5, 1.0, 0.0, 1.0);
+        assert!(matches!(
+            generator.render_to_buffer(8000, 0.0),
+            Err(RenderError::InvalidTime { index: 1, .. })
+        ));
+        generator.elements[1].time = 100.0;
+        let window = generator.render_range(8000, 0.0, 1.0).unwrap();
+        assert_eq!(window.len(), 8000);
+        assert!(window[4000..].iter().all(|&sample| sample == 0.0));
+        let stereo = generator.render_stereo_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(stereo.len(), 2 * generator.sample_count(8000, 0.0));
+    }
+
+    #[test]
+    fn element_fade_stays_within_its_region() {
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        generator.add_music_element(WaveFunction::Square, 0.0, 0.5, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Square, 0.5, 0.5, 0.0, 1.0);
+        let abrupt = generator.render_to_buffer(8000, 0.0).unwrap();
+        generator.elements[1].fade_ms = 20.0;
+        let faded = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(&faded[..4000], &abrupt[..4000]);
+        assert!(faded[4000].abs() < 0.01);
+        assert!(faded[4080].abs() > faded[4010].abs());
+        assert!(faded[4010].abs() < abrupt[4010].abs());
+        assert_eq!(&faded[4160..7840], &abrupt[4160..7840]);
+        assert!(faded[7999].abs() < 0.01);
+    }
+
+    #[test]
+    fn pluck_starts_bright_and_decays() {
+        let mut generator = Generator::with_seed(4);
+        generator.add_music_element(WaveFunction::Pluck, 0.0, 2.0, 0.0, 1.0);
+        let wave = generator.render_to_buffer(16000, 0.0).unwrap();
+        let brightness = |wave: &[f32]| {
+            let steps: Vec<f32> = wave.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            effects::rms(&steps) / effects::rms(wave)
+        };
+        let start = &wave[..1600];
+        let late = &wave[16000..17600];
+        assert!(effects::rms(late) < 0.5 * effects::rms(start));
+        assert!(effects::rms(&wave[30000..31600]) < effects::rms(late));
+        assert!(brightness(start) > 1.5 * brightness(late));
+    }
+
+    #[test]
+    fn roll_spaces_hits_with_decaying_amplitude() {
+        let mut generator = Generator::new();
+        generator.add_roll(WaveFunction::WhiteNoise, 1.0, 1.0, 8, 0.0, 0.8);
+        assert_eq!(generator.element_count(), 8);
+        for (hit, element) in generator.elements.iter().enumerate() {
+            assert!((element.time - (1.0 + hit as f32 * 0.125)).abs() < 1e-6);
+            assert!((element.duration - 0.125).abs() < 1e-6);
+        }
+        assert!(generator
+            .elements
+            .windows(2)
+            .all(|pair| pair[1].amplitude < pair[0].amplitude));
+        assert!((generator.elements[7].amplitude - 0.8f32.powi(7)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn harmony_offsets_every_note_by_interval() {
+        let mut melody = Generator::new();
+        for (index, note) in [0.0, 2.0, 4.0, 5.0].iter().enumerate() {
+            melody.add_music_element(WaveFunction::Square, index as f32 * 0.5, 0.5, *note, 1.0);
+        }
+        let harmony = melody.harmonize(4.0, WaveFunction::Sine, 0.5);
+        assert_eq!(harmony.element_count(), melody.element_count());
+        for (voice, original) in harmony.elements.iter().zip(melody.elements.iter()) {
+            assert!(matches!(voice.function, WaveFunction::Sine));
+            assert_eq!(
+                (voice.time, voice.duration),
+                (original.time, original.duration)
+            );
+            assert_eq!(voice.amplitude, 0.5);
+            assert!(matches!(
+                (&voice.pitch, &original.pitch),
+                (Pitch::Note(note), Pitch::Note(melody_note)) if *note == melody_note + 4.0
+            ));
+        }
+    }
+
+    #[test]
+    fn unison_spreads_voices_symmetrically() {
+        let mut generator = Generator::new();
+        generator.add_unison(WaveFunction::Sawtooth, 0.0, 1.0, 3.0, 5, 40.0, 1.0);
+        assert_eq!(generator.element_count(), 5);
+        let detunes: Vec<f32> = generator
+            .elements
+            .iter()
+            .map(|element| element.detune_cents)
+            .collect();
+        assert_eq!(detunes, vec![-20.0, -10.0, 0.0, 10.0, 20.0]);
+        assert!(generator
+            .elements
+            .iter()
+            .all(|element| element.amplitude == 0.2
+                && matches!(element.pitch, Pitch::Note(note) if note == 3.0)));
+    }
+
+    #[test]
+    fn feel_shifts_elements_without_going_negative() {
+        let mut generator = Generator::new();
+        for time in [0.01, 0.5, 1.0] {
+            generator.add_music_element(WaveFunction::Sine, time, 0.25, 0.0, 1.0);
+        }
+        generator.apply_feel(0.02);
+        let times: Vec<f32> = generator
+            .elements
+            .iter()
+            .map(|element| element.time)
+            .collect();
+        assert_eq!(times, vec![0.03, 0.52, 1.02]);
+        generator.apply_feel(-0.05);
+        let times: Vec<f32> = generator
+            .elements
+            .iter()
+            .map(|element| element.time)
+            .collect();
+        assert_eq!(times[0], 0.0);
+        assert!((times[1] - 0.47).abs() < 1e-6 && (times[2] - 0.97).abs() < 1e-6);
+    }
+
+    #[test]
+    fn half_cycle_phase_offset_cancels() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+        generator
+            .add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0)
+            .phase_offset = 0.5;
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert!(effects::analyze(&wave).peak < 1e-3);
+        generator.elements[1].phase_offset = 0.0;
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert!(effects::analyze(&wave).peak > 0.9);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn batch_renders_every_job() {
+        let settings = RenderSettings {
+            sample_rate: 8000,
+            silence_time: 0.0,
+            bit_depth: BitDepth::Int16,
+        };
+        let mut jobs = Vec::new();
+        for (index, time) in [0.0, -1.0, 0.25].iter().enumerate() {
+            let mut generator = Generator::new();
+            generator.add_music_element(WaveFunction::Sine, *time, 0.25, 0.0, 1.0);
+            let path = std::env::temp_dir().join(format!("music-test-batch-{}.wav", index));
+            jobs.push((generator, settings, path.to_str().unwrap().to_string()));
+        }
+        let results = render_batch(&jobs);
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[1], Err(RenderError::InvalidTime { .. })));
+        for (index, expected) in [(0, 2000), (2, 4000)] {
+            assert!(results[index].is_ok());
+            let path = &jobs[index].2;
+            assert_eq!(hound::WavReader::open(path).unwrap().len(), expected);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn ritardando_delays_later_beats() {
+        let beat_four = |points: &[(f32, f32)]| {
+            let mut generator = Generator::new();
+            generator.tempo = Some(Tempo::new(120.0));
+            generator.add_note_at_beat(WaveFunction::Sine, 4.0, 1.0, 0.0, 1.0);
+            generator.apply_tempo_curve(points);
+            generator.elements[0].time
+        };
+        let constant = beat_four(&[(0.0, 120.0)]);
+        let slowing = beat_four(&[(0.0, 120.0), (4.0, 60.0)]);
+        assert!((constant - 2.0).abs() < 1e-5);
+        assert!(slowing > constant);
+        assert!((slowing - 4.0 * 2f32.ln()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn mono_mode_cuts_previous_note() {
+        let mut generator = Generator::new();
+        generator.click_reduction_ms = 0.0;
+        generator.add_music_element(WaveFunction::Square, 0.0, 1.0, 0.0, 0.5);
+        generator.add_music_element(WaveFunction::Square, 0.5, 1.0, 7.0, 0.5);
+        let mut second = Generator::new();
+        second.click_reduction_ms = 0.0;
+        second.elements = generator.elements[1..].to_vec();
+        let alone = second.render_to_buffer(8000, 0.0).unwrap();
+        let poly = generator.render_to_buffer(8000, 0.0).unwrap();
+        generator.mono = true;
+        let mono = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(&mono[4000..8000], &alone[4000..8000]);
+        assert_ne!(&poly[4000..8000], &alone[4000..8000]);
+        assert_eq!(&mono[..4000], &poly[..4000]);
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn metadata_sidecar_matches_generator() {
+        let mut generator = Generator::new();
+        generator.tempo = Some(Tempo::new(100.0));
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Square, 0.25, 1.0, 3.0, 0.5);
+        generator.add_rest(1.25, 0.5);
+        let directory = std::env::temp_dir();
+        let wav_path = directory.join("music-test-meta.wav");
+        let meta_path = directory.join("music-test-meta.json");
+        let (wav_path, meta_path) = (wav_path.to_str().unwrap(), meta_path.to_str().unwrap());
+        generator
+            .render_with_metadata(8000, 0.0, wav_path, meta_path, BitDepth::Int16)
+            .unwrap();
+        let metadata: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(meta_path).unwrap()).unwrap();
+        assert_eq!(metadata["element_count"], 3);
+        assert_eq!(
+            metadata["total_duration"],
+            generator.total_duration() as f64
+        );
+        assert_eq!(metadata["tempo_bpm"], 100.0);
+        assert_eq!(metadata["notes"].as_array().unwrap().len(), 2);
+        std::fs::remove_file(wav_path).unwrap();
+        std::fs::remove_file(meta_path).unwrap();
+    }
+
+    #[test]
+    fn average_mode_keeps_unison_at_single_level() {
+        let render = |count: usize, mix_mode: MixMode| {
+            let mut generator = Generator::new();
+            generator.mix_mode = mix_mode;
+            for _ in 0..count {
+                generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 0.5);
+            }
+            generator.render_to_buffer(8000, 0.0).unwrap()
+        };
+        let single = render(1, MixMode::Sum);
+        let summed = render(2, MixMode::Sum);
+        let averaged = render(2, MixMode::Average);
+        let loudest = render(2, MixMode::Max);
+        for index in 0..single.len() {
+            assert!((summed[index] - 2.0 * single[index]).abs() < 1e-6);
+            assert!((averaged[index] - single[index]).abs() < 1e-6);
+            assert!((loudest[index] - single[index]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn probability_selects_a_seeded_subset() {
+        let played = |seed: u64, probability: f32| -> Vec<bool> {
+            let mut generator = Generator::with_seed(seed);
+            for index in 0..16 {
+                generator
+                    .add_music_element(WaveFunction::Square, index as f32 * 0.1, 0.1, 0.0, 1.0)
+                    .probability = probability;
+            }
+            let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+            wave.chunks(800)
+                .map(|slot| slot.iter().any(|&sample| sample != 0.0))
+                .collect()
+        };
+        let subset = played(21, 0.5);
+        assert_eq!(subset, played(21, 0.5));
+        let count = subset.iter().filter(|&&sounded| sounded).count();
+        assert!(count > 2 && count < 14, "{} of 16 played", count);
+        assert!(played(21, 0.0).iter().all(|&sounded| !sounded));
+        assert!(played(21, 1.0).iter().all(|&sounded| sounded));
+    }
+
+    #[test]
+    fn rising_filter_envelope_brightens_note() {
+        let mut generator = Generator::new();
+        let element = generator.add_music_element(WaveFunction::Sawtooth, 0.0, 1.0, -24.0, 1.0);
+        element.envelope = Some(Envelope::new(1.0, 0.0, 1.0, 0.0));
+        element.filter_cutoff = Some(150.0);
+        element.filter_env_amount = 5.0;
+        let wave = generator.render_to_buffer(16000, 0.0).unwrap();
+        let brightness = |wave: &[f32]| {
+            let steps: Vec<f32> = wave.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            effects::rms(&steps) / effects::rms(wave)
+        };
+        let start = &wave[2000..4000];
+        let end = &wave[13000..15000];
+        assert!(brightness(end) > 2.0 * brightness(start));
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn empty_generator_writes_readable_wav() {
+        let generator = Generator::new();
+        let path = std::env::temp_dir().join("music-test-empty.wav");
+        let path = path.to_str().unwrap();
+        generator.render(8000, 0.0, path, BitDepth::Int16).unwrap();
+        let reader = hound::WavReader::open(path).unwrap();
+        assert_eq!(reader.len(), 0);
+        assert_eq!(reader.spec().sample_rate, 8000);
+        std::fs::remove_file(path).unwrap();
+        assert!(generator.render_to_buffer(8000, 0.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn snap_to_scale_rounds_to_nearest_degree() {
+        let root = -9.0;
+        let mut generator = Generator::new();
+        for offset in [6.3, 3.4, 10.6, 11.8, -2.7] {
+            generator.add_music_element(WaveFunction::Sine, 0.0, 1.0, root + offset, 1.0);
+        }
+        generator.snap_to_scale(root, Scale::Major);
+        let notes: Vec<f32> = generator
+            .elements
+            .iter()
+            .map(|element| match element.pitch {
+                Pitch::Note(note) => note - root,
+                Pitch::Hz(_) => f32::NAN,
+            })
+            .collect();
+        assert_eq!(notes, vec![7.0, 4.0, 11.0, 12.0, -3.0]);
+    }
+
+    #[test]
+    fn noise_shaping_moves_error_to_high_frequencies() {
+        let full_scale = i16::MAX as f32;
+        let signal: Vec<f32> = (0..8192)
+            .map(|index| 10.0 / full_scale * oscillator::sine(index as f32 * 0.013))
+            .collect();
+        let tilt = |mode: DitherMode| {
+            let mut dither = Dither::new(mode, 8);
+            let error: Vec<f32> = signal
+                .iter()
+                .map(|&sample| quantize(sample, 16, Some(&mut dither)) as f32 - sample * full_scale)
+                .collect();
+            let bins = effects::spectrum(&error, 0, error.len(), 8192.0);
+            let band = |low: f32, high: f32| -> f32 {
+                bins.iter()
+                    .filter(|(frequency, _)| *frequency >= low && *frequency < high)
+                    .map(|(_, magnitude)| magnitude * magnitude)
+                    .sum()
+            };
+            band(3072.0, 4096.0) / band(0.0, 1024.0)
+        };
+        let triangular = tilt(DitherMode::Triangular);
+        let shaped = tilt(DitherMode::NoiseShaped);
+        assert!(triangular < 2.0, "triangular tilt {}", triangular);
+        assert!(shaped > 4.0 * triangular, "shaped tilt {}", shaped);
+    }
+
+    #[test]
+    fn groove_offsets_follow_grid_slots() {
+        let mut generator = Generator::new();
+        for slot in 0..6 {
+            generator.add_music_element(WaveFunction::Sine, slot as f32 * 0.25, 0.2, 0.0, 1.0);
+        }
+        generator.apply_groove(&[-0.2, 0.1, 0.0], 0.25);
+        let times: Vec<f32> = generator
+            .elements
+            .iter()
+            .map(|element| element.time)
+            .collect();
+        let expected = [0.0, 0.275, 0.5, 0.7, 1.025, 1.25];
+        for (time, expected) in times.iter().zip(expected) {
+            assert!((time - expected).abs() < 1e-6, "{} vs {}", time, expected);
+        }
+    }
+}
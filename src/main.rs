@@ -1,121 +1,41 @@
-use hound;
-use std::collections::LinkedList;
+use music::composition::parse_composition;
+use music::{BitDepth, Generator};
 
-enum WaveFunction {
-    Square,
-    Sawtooth,
-    Triangle,
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1);
 }
 
-struct MusicElement {
-    function: WaveFunction,
-    time: f32,
-    duration: f32,
-    note: f32,
-}
-
-impl MusicElement {
-    fn new(function: WaveFunction, time: f32, duration: f32, note: f32) -> MusicElement {
-        MusicElement {
-            function,
-            time,
-            duration,
-            note,
-        }
-    }
-}
-
-struct Generator {
-    elements: LinkedList<MusicElement>,
-}
-
-fn apply_wave_function(function: &WaveFunction, t: f32, f: f32) -> f32 {
-    match function {
-        WaveFunction::Square => square(t, f),
-        WaveFunction::Sawtooth => sawtooth(t, f),
-        WaveFunction::Triangle => triangle(t, f),
-    }
-}
-
-impl Generator {
-    fn new() -> Generator {
-        let elements = LinkedList::new();
-        Generator { elements }
-    }
-    fn add_music_element(&mut self, function: WaveFunction, time: f32, duration: f32, note: f32) {
-        let element = MusicElement::new(function, time, duration, note);
-        &self.elements.push_back(element);
-    }
-    fn render_elements(&self, sample_rate: f32, wave: &mut Vec<f32>) {
-        for element in self.elements.iter() {
-            let first_sample = (sample_rate * element.time) as usize;
-            let last_sample = first_sample + (sample_rate * element.duration) as usize;
-            let frequency = get_frequency_from_note(element.note);
-            let mut sample_time = 0f32;
-            for sample in first_sample..last_sample {
-                let t = sample_time / sample_rate;
-                let level = apply_wave_function(&element.function, t, frequency);
-                wave[sample] += level;
-                sample_time += 1.0;
-            }
-        }
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 || args.len() > 5 {
+        eprintln!(
+            "usage: {} <input.json|input.txt> <output.wav> [sample_rate] [silence_time]",
+            args[0]
+        );
+        std::process::exit(2);
     }
-    fn create_wave(&self, sample_rate: u32, silence_time: f32) -> Vec<f32> {
-        let mut global_time = 0f32;
-        for element in self.elements.iter() {
-            let end_time = element.time + element.duration;
-            if end_time > global_time {
-                global_time = end_time;
-            }
-        }
-        global_time += silence_time;
-        let sample_rate_f32 = sample_rate as f32;
-        let samples_count_f32 = global_time * sample_rate_f32;
-        let samples_count = samples_count_f32 as usize;
-        let mut wave: Vec<f32> = Vec::with_capacity(samples_count);
-        for _i in 0..samples_count {
-            wave.push(0.0);
-        }
-        wave
+    let sample_rate = match args.get(3) {
+        Some(sample_rate) => sample_rate
+            .parse()
+            .unwrap_or_else(|_| exit_with_error(&format!("invalid sample rate {}", sample_rate))),
+        None => 48000,
+    };
+    let silence_time = match args.get(4) {
+        Some(silence_time) => silence_time
+            .parse()
+            .unwrap_or_else(|_| exit_with_error(&format!("invalid silence time {}", silence_time))),
+        None => 0.0,
+    };
+    let source = std::fs::read_to_string(&args[1])
+        .unwrap_or_else(|error| exit_with_error(&format!("cannot read {}: {}", args[1], error)));
+    let generator = if args[1].ends_with(".json") {
+        Generator::from_json(&source).map_err(|error| error.to_string())
+    } else {
+        parse_composition(&source).map_err(|error| error.to_string())
     }
-    fn render(&self, sample_rate: u32, silence_time: f32, file_name: &str) {
-        let mut wave = self.create_wave(sample_rate, silence_time);
-        self.render_elements(sample_rate as f32, &mut wave);
-        let wav_spec = hound::WavSpec {
-            channels: 1,
-            sample_rate: sample_rate,
-            bits_per_sample: 32,
-            sample_format: hound::SampleFormat::Float,
-        };
-        let mut writer = hound::WavWriter::create(file_name, wav_spec).unwrap();
-        for sample in wave {
-            writer.write_sample(sample).unwrap();
-        }
+    .unwrap_or_else(|error| exit_with_error(&format!("invalid composition: {}", error)));
+    if let Err(error) = generator.render(sample_rate, silence_time, &args[2], BitDepth::Float32) {
+        exit_with_error(&format!("failed to render {}: {}", args[2], error));
     }
 }
-
-fn square(t: f32, f: f32) -> f32 {
-    return 2.0 * (2.0 * (t * f).floor() - (2.0 * t * f).floor()) + 1.0;
-}
-
-fn sawtooth(t: f32, f: f32) -> f32 {
-    return 2.0 * (t * f - (1.0 / 2.0 + t * f).floor());
-}
-
-fn triangle(t: f32, f: f32) -> f32 {
-    return 2.0 * (2.0 * (t * f - (1.0 / 2.0 + t * f).floor())).abs() - 1.0;
-}
-
-fn get_frequency_from_note(note: f32) -> f32 {
-    return 440.0 * 2.0f32.powf(note / 12.0);
-}
-
-fn main() {
-    let mut generator = Generator::new();
-    generator.add_music_element(WaveFunction::Square, 0.0, 1.0, 0.0);
-    generator.add_music_element(WaveFunction::Square, 1.0, 1.0, -6.0);
-    generator.add_music_element(WaveFunction::Square, 3.0, 1.0, 0.0);
-    generator.add_music_element(WaveFunction::Square, 5.0, 1.0, -6.0);
-
-    generator.render(48000, 0.0, "music.wav");
-}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+use midly::num::{u15, u24, u28, u4, u7};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+
+use crate::pitch::{note_from_frequency, Pitch};
+use crate::{Generator, WaveFunction};
+
+pub const TICKS_PER_BEAT: u16 = 480;
+const A4_KEY: f32 = 69.0;
+
+#[derive(Debug)]
+pub enum MidiError {
+    Io(io::Error),
+    Parse(midly::Error),
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MidiError::Io(error) => write!(f, "{}", error),
+            MidiError::Parse(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for MidiError {}
+
+impl From<io::Error> for MidiError {
+    fn from(error: io::Error) -> MidiError {
+        MidiError::Io(error)
+    }
+}
+
+impl From<midly::Error> for MidiError {
+    fn from(error: midly::Error) -> MidiError {
+        MidiError::Parse(error)
+    }
+}
+
+fn seconds_to_ticks(seconds: f32, tempo_bpm: f32) -> u32 {
+    (seconds.max(0.0) * tempo_bpm / 60.0 * TICKS_PER_BEAT as f32).round() as u32
+}
+
+pub fn write_midi(generator: &Generator, path: &str, tempo_bpm: f32) -> Result<(), MidiError> {
+    let mut notes = Vec::new();
+    for element in generator.elements.iter() {
+        if let WaveFunction::Rest = element.function {
+            continue;
+        }
+        let note = match element.pitch {
+            Pitch::Note(note) => note,
+            Pitch::Hz(frequency) => note_from_frequency(frequency, generator.tuning_reference),
+        };
+        if note.is_nan() {
+            continue;
+        }
+        let key = u7::new((note + A4_KEY).round().clamp(0.0, 127.0) as u8);
+        let velocity = u7::new((element.amplitude.clamp(0.0, 1.0) * 127.0).round().max(1.0) as u8);
+        let start = seconds_to_ticks(element.time, tempo_bpm);
+        let end = seconds_to_ticks(element.time + element.duration, tempo_bpm).max(start + 1);
+        notes.push((start, MidiMessage::NoteOn { key, vel: velocity }));
+        notes.push((
+            end,
+            MidiMessage::NoteOff {
+                key,
+                vel: u7::new(0),
+            },
+        ));
+    }
+    notes.sort_by_key(|(tick, message)| (*tick, matches!(message, MidiMessage::NoteOn { .. })));
+    let tempo = ((60_000_000.0 / tempo_bpm) as u32).min(0xFF_FFFF);
+    let mut track = vec![TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::Tempo(u24::new(tempo))),
+    }];
+    let mut previous = 0;
+    for (tick, message) in notes {
+        track.push(TrackEvent {
+            delta: u28::new(tick - previous),
+            kind: TrackEventKind::Midi {
+                channel: u4::new(0),
+                message,
+            },
+        });
+        previous = tick;
+    }
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    let mut smf = Smf::new(Header::new(
+        Format::SingleTrack,
+        Timing::Metrical(u15::new(TICKS_PER_BEAT)),
+    ));
+    smf.tracks.push(track);
+    smf.save(path)?;
+    Ok(())
+}
+
+/// Times use the given tempo and resolution rather than the file's own meta events.
+pub fn read_midi(
+    path: &str,
+    function: WaveFunction,
+    ticks_per_beat: u16,
+    tempo_bpm: f32,
+) -> Result<Generator, MidiError> {
+    let data = std::fs::read(path)?;
+    let smf = Smf::parse(&data)?;
+    let ticks_to_seconds =
+        |ticks: u32| ticks as f32 / ticks_per_beat.max(1) as f32 * 60.0 / tempo_bpm;
+    let mut generator = Generator::new();
+    let mut notes = Vec::new();
+    for track in smf.tracks.iter() {
+        let mut tick = 0u32;
+        let mut held: HashMap<(u8, u8), Vec<(u32, u8)>> = HashMap::new();
+        for event in track.iter() {
+            tick += event.delta.as_int();
+            let (channel, message) = match event.kind {
+                TrackEventKind::Midi { channel, message } => (channel.as_int(), message),
+                _ => continue,
+            };
+            match message {
+                MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                    held.entry((channel, key.as_int()))
+                        .or_default()
+                        .push((tick, vel.as_int()));
+                }
+                MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                    if let Some(starts) = held.get_mut(&(channel, key.as_int())) {
+                        if !starts.is_empty() {
+                            let (start, velocity) = starts.remove(0);
+                            notes.push((start, tick, key.as_int(), velocity));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for ((_, key), starts) in held {
+            for (start, velocity) in starts {
+                notes.push((start, tick, key, velocity));
+            }
+        }
+    }
+    notes.sort_unstable();
+    for (start, end, key, velocity) in notes {
+        let time = ticks_to_seconds(start);
+        generator.add_music_element(
+            function.clone(),
+            time,
+            ticks_to_seconds(end) - time,
+            key as f32 - A4_KEY,
+            velocity as f32 / 127.0,
+        );
+    }
+    Ok(generator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_note_events_with_pitches() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Square, 0.0, 0.5, 0.0, 1.0);
+        generator.add_music_element(WaveFunction::Sine, 0.5, 0.5, 3.0, 0.5);
+        generator.add_rest(1.0, 0.5);
+        generator.add_music_element(WaveFunction::Sine, 1.5, 0.5, -12.0, 0.5);
+        let path = std::env::temp_dir().join("music-test-export.mid");
+        let path = path.to_str().unwrap();
+        write_midi(&generator, path, 120.0).unwrap();
+        let data = std::fs::read(path).unwrap();
+        let smf = Smf::parse(&data).unwrap();
+        let keys: Vec<u8> = smf.tracks[0]
+            .iter()
+            .filter_map(|event| match event.kind {
+                TrackEventKind::Midi {
+                    message: MidiMessage::NoteOn { key, .. },
+                    ..
+                } => Some(key.as_int()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(keys, vec![69, 72, 57]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn round_trips_exported_file() {
+        let mut original = Generator::new();
+        original.add_music_element(WaveFunction::Square, 0.0, 0.5, 0.0, 1.0);
+        original.add_music_element(WaveFunction::Sine, 0.25, 0.75, 7.0, 0.5);
+        original.add_music_element(WaveFunction::Sine, 1.0, 0.25, -5.0, 0.25);
+        let path = std::env::temp_dir().join("music-test-round-trip.mid");
+        let path = path.to_str().unwrap();
+        write_midi(&original, path, 90.0).unwrap();
+        let imported = read_midi(path, WaveFunction::Triangle, TICKS_PER_BEAT, 90.0).unwrap();
+        assert_eq!(imported.element_count(), 3);
+        for (imported, original) in imported.elements.iter().zip(original.elements.iter()) {
+            assert!(matches!(imported.function, WaveFunction::Triangle));
+            assert!((imported.time - original.time).abs() < 1e-3);
+            assert!((imported.duration - original.duration).abs() < 1e-3);
+            assert!(matches!(
+                (&imported.pitch, &original.pitch),
+                (Pitch::Note(note), Pitch::Note(expected)) if note == expected
+            ));
+            assert!((imported.amplitude - original.amplitude).abs() < 0.01);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::effects;
+use crate::error::RenderError;
+use crate::Generator;
+#[cfg(feature = "fs")]
+use crate::{write_wav, BitDepth};
+
+pub struct Track {
+    pub generator: Generator,
+    pub gain: f32,
+}
+
+pub struct Sidechain {
+    pub trigger_track: usize,
+    pub target_track: usize,
+    pub amount: f32,
+    pub release_ms: f32,
+}
+
+pub struct Mixer {
+    pub tracks: Vec<Track>,
+    pub master_gain: f32,
+    pub sidechains: Vec<Sidechain>,
+    /// Output peak, in dBFS, that `auto_gain` aims for.
+    pub ceiling_db: f32,
+}
+
+impl Mixer {
+    pub fn new() -> Mixer {
+        Mixer {
+            tracks: Vec::new(),
+            master_gain: 1.0,
+            sidechains: Vec::new(),
+            ceiling_db: -1.0,
+        }
+    }
+    pub fn add_track(&mut self, generator: Generator, gain: f32) {
+        self.tracks.push(Track { generator, gain });
+    }
+    pub fn sidechain(
+        &mut self,
+        trigger_track: usize,
+        target_track: usize,
+        amount: f32,
+        release_ms: f32,
+    ) {
+        self.sidechains.push(Sidechain {
+            trigger_track,
+            target_track,
+            amount,
+            release_ms,
+        });
+    }
+    /// Sets `master_gain` so the summed output peaks at `ceiling_db`; track gains are
+    /// left alone so the balance between tracks is unchanged.
+    pub fn auto_gain(&mut self, sample_rate: u32) -> Result<(), RenderError> {
+        self.master_gain = 1.0;
+        let peak = effects::analyze(&self.render(sample_rate, 0.0)?).peak;
+        if peak > 0.0 {
+            self.master_gain = 10f32.powf(self.ceiling_db / 20.0) / peak;
+        }
+        Ok(())
+    }
+    pub fn render(&self, sample_rate: u32, silence_time: f32) -> Result<Vec<f32>, RenderError> {
+        let mut mix: Vec<f32> = Vec::new();
+        for wave in self.render_tracks(sample_rate, silence_time)? {
+            if wave.len() > mix.len() {
+                mix.resize(wave.len(), 0.0);
+            }
+            for (mixed, sample) in mix.iter_mut().zip(wave.iter()) {
+                *mixed += sample;
+            }
+        }
+        for sample in mix.iter_mut() {
+            *sample *= self.master_gain;
+        }
+        Ok(mix)
+    }
+    /// Writes each track, after its gain and sidechain ducking but before `master_gain`, to
+    /// `track_<index>.wav` in `dir`. Stems are padded to the longest track so they line up.
+    #[cfg(feature = "fs")]
+    pub fn render_stems(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+        dir: &str,
+    ) -> Result<(), RenderError> {
+        let mut waves = self.render_tracks(sample_rate, silence_time)?;
+        let length = waves.iter().map(Vec::len).max().unwrap_or(0);
+        for (index, wave) in waves.iter_mut().enumerate() {
+            wave.resize(length, 0.0);
+            let path = Path::new(dir).join(format!("track_{}.wav", index));
+            write_wav(
+                &path.to_string_lossy(),
+                wave,
+                sample_rate,
+                1,
+                BitDepth::Float32,
+                None,
+            )?;
+        }
+        Ok(())
+    }
+    fn render_tracks(
+        &self,
+        sample_rate: u32,
+        silence_time: f32,
+    ) -> Result<Vec<Vec<f32>>, RenderError> {
+        let mut waves = Vec::with_capacity(self.tracks.len());
+        for track in self.tracks.iter() {
+            let mut wave = track
+                .generator
+                .render_to_buffer(sample_rate, silence_time)?;
+            for sample in wave.iter_mut() {
+                *sample *= track.gain;
+            }
+            waves.push(wave);
+        }
+        for sidechain in self.sidechains.iter() {
+            if sidechain.trigger_track == sidechain.target_track
+                || sidechain.trigger_track >= waves.len()
+                || sidechain.target_track >= waves.len()
+            {
+                continue;
+            }
+            let trigger = waves[sidechain.trigger_track].clone();
+            duck(
+                &mut waves[sidechain.target_track],
+                &trigger,
+                sidechain.amount,
+                sidechain.release_ms,
+                sample_rate as f32,
+            );
+        }
+        Ok(waves)
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Mixer {
+        Mixer::new()
+    }
+}
+
+fn duck(target: &mut [f32], trigger: &[f32], amount: f32, release_ms: f32, sample_rate: f32) {
+    let amount = amount.clamp(0.0, 1.0);
+    let envelope = effects::envelope_follower(trigger, 0.0, release_ms, sample_rate);
+    for (sample, envelope) in target.iter_mut().zip(envelope) {
+        *sample *= 1.0 - amount * envelope.min(1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WaveFunction;
+
+    #[test]
+    fn duck_follows_trigger_and_releases() {
+        let mut target = vec![1.0; 2000];
+        let mut trigger = vec![0.0; 2000];
+        trigger[..100].iter_mut().for_each(|sample| *sample = 1.0);
+        duck(&mut target, &trigger, 0.5, 10.0, 1000.0);
+        assert!((target[50] - 0.5).abs() < 1e-6);
+        assert!(target[110] > target[101]);
+        assert!(target[1999] > 0.999);
+    }
+
+    #[test]
+    fn tracks_sum_with_gains_to_longest_length() {
+        let mut short = Generator::new();
+        short.add_music_element(WaveFunction::Sine, 0.0, 0.25, 0.0, 1.0);
+        let mut long = Generator::new();
+        long.add_music_element(WaveFunction::Sine, 0.0, 0.5, 7.0, 1.0);
+        let short_wave = short.render_to_buffer(8000, 0.0).unwrap();
+        let long_wave = long.render_to_buffer(8000, 0.0).unwrap();
+        let mut mixer = Mixer::new();
+        mixer.add_track(short, 0.5);
+        mixer.add_track(long, 0.5);
+        let mix = mixer.render(8000, 0.0).unwrap();
+        assert_eq!(mix.len(), long_wave.len());
+        for (index, sample) in mix.iter().enumerate() {
+            let expected = 0.5 * short_wave.get(index).unwrap_or(&0.0) + 0.5 * long_wave[index];
+            assert!((sample - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn sidechain_ducks_target_while_trigger_plays() {
+        let mut kick = Generator::new();
+        kick.click_reduction_ms = 0.0;
+        kick.add_music_element(WaveFunction::Square, 0.0, 0.1, 0.0, 1.0);
+        kick.add_rest(0.1, 0.9);
+        let mut pad = Generator::new();
+        pad.add_music_element(WaveFunction::Sine, 0.0, 1.0, 0.0, 1.0);
+        let dry = pad.render_to_buffer(8000, 0.0).unwrap();
+        let mut mixer = Mixer::new();
+        mixer.add_track(kick, 1.0);
+        mixer.add_track(pad, 1.0);
+        mixer.sidechain(0, 1, 0.8, 20.0);
+        let ducked = &mixer.render_tracks(8000, 0.0).unwrap()[1];
+        let level = |range: std::ops::Range<usize>| {
+            effects::rms(&ducked[range.clone()]) / effects::rms(&dry[range])
+        };
+        assert!((level(200..600) - 0.2).abs() < 0.02);
+        assert!(level(900..1000) > level(200..600));
+        assert!(level(4000..6000) > 0.99);
+    }
+
+    #[test]
+    fn auto_gain_hits_ceiling_and_keeps_balance() {
+        for level in [0.1, 0.9] {
+            let mut quiet = Generator::new();
+            quiet.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+            let mut loud = Generator::new();
+            loud.add_music_element(WaveFunction::Square, 0.0, 0.5, 7.0, 1.0);
+            let mut mixer = Mixer::new();
+            mixer.add_track(quiet, level);
+            mixer.add_track(loud, 3.0 * level);
+            mixer.auto_gain(8000).unwrap();
+            let peak = effects::analyze(&mixer.render(8000, 0.0).unwrap()).peak;
+            assert!(
+                (peak - 10f32.powf(-1.0 / 20.0)).abs() < 1e-4,
+                "peak {}",
+                peak
+            );
+            assert_eq!(mixer.tracks[1].gain, 3.0 * mixer.tracks[0].gain);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fs")]
+    fn stems_share_the_longest_length() {
+        let dir = std::env::temp_dir().join("music-test-stems");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut mixer = Mixer::new();
+        for duration in [0.25, 0.5, 0.125] {
+            let mut generator = Generator::new();
+            generator.add_music_element(WaveFunction::Sine, 0.0, duration, 0.0, 1.0);
+            mixer.add_track(generator, 1.0);
+        }
+        mixer
+            .render_stems(8000, 0.0, dir.to_str().unwrap())
+            .unwrap();
+        for index in 0..3 {
+            let path = dir.join(format!("track_{}.wav", index));
+            let reader = hound::WavReader::open(&path).unwrap();
+            assert_eq!(reader.len(), 4000);
+        }
+        assert!(!dir.join("track_3.wav").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,516 @@
+//! Oscillators take a phase measured in cycles, so one period spans `0.0..1.0`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::rng::Rng;
+use crate::WaveFunction;
+
+/// ```
+/// use music::oscillator::square;
+/// assert_eq!(square(0.25), 1.0);
+/// assert_eq!(square(0.75), -1.0);
+/// ```
+pub fn square(phase: f32) -> f32 {
+    2.0 * (2.0 * phase.floor() - (2.0 * phase).floor()) + 1.0
+}
+
+/// ```
+/// use music::oscillator::sawtooth;
+/// assert_eq!(sawtooth(0.25), 0.5);
+/// assert_eq!(sawtooth(0.75), -0.5);
+/// ```
+pub fn sawtooth(phase: f32) -> f32 {
+    2.0 * (phase - (1.0 / 2.0 + phase).floor())
+}
+
+/// ```
+/// use music::oscillator::triangle;
+/// assert_eq!(triangle(0.0), -1.0);
+/// assert_eq!(triangle(0.5), 1.0);
+/// ```
+pub fn triangle(phase: f32) -> f32 {
+    2.0 * (2.0 * (phase - (1.0 / 2.0 + phase).floor())).abs() - 1.0
+}
+
+/// ```
+/// use music::oscillator::pulse;
+/// assert_eq!(pulse(0.1, 0.25), 1.0);
+/// assert_eq!(pulse(0.5, 0.25), -1.0);
+/// ```
+pub fn pulse(phase: f32, duty: f32) -> f32 {
+    if phase < duty.clamp(0.01, 0.99) {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+/// ```
+/// use music::oscillator::sine;
+/// assert!((sine(0.25) - 1.0).abs() < 1e-6);
+/// ```
+pub fn sine(phase: f32) -> f32 {
+    (2.0 * std::f32::consts::PI * phase).sin()
+}
+
+/// Logarithmic sweeps need positive frequencies and fall back to linear otherwise.
+pub fn sweep(
+    start_hz: f32,
+    end_hz: f32,
+    duration: f32,
+    sample_rate: u32,
+    logarithmic: bool,
+) -> Vec<f32> {
+    let sample_count = (duration.max(0.0) * sample_rate as f32).ceil() as usize;
+    let logarithmic = logarithmic && start_hz > 0.0 && end_hz > 0.0;
+    let mut wave = Vec::with_capacity(sample_count);
+    let mut phase = 0f32;
+    for sample in 0..sample_count {
+        let progress = sample as f32 / sample_count as f32;
+        let frequency = if logarithmic {
+            start_hz * (end_hz / start_hz).powf(progress)
+        } else {
+            start_hz + (end_hz - start_hz) * progress
+        };
+        wave.push(sine(phase));
+        phase += frequency / sample_rate as f32;
+        phase -= phase.floor();
+    }
+    wave
+}
+
+/// Harmonic amplitudes are normalized by their absolute sum, so the peak stays within 1.
+///
+/// ```
+/// use music::oscillator::{additive, sine};
+/// assert_eq!(additive(0.1, &[1.0]), sine(0.1));
+/// ```
+pub fn additive(phase: f32, harmonics: &[f32]) -> f32 {
+    let total: f32 = harmonics.iter().map(|amplitude| amplitude.abs()).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    let mut level = 0.0;
+    for (harmonic, amplitude) in harmonics.iter().enumerate() {
+        level += amplitude * sine(phase * (harmonic + 1) as f32);
+    }
+    level / total
+}
+
+pub fn sample_at(data: &[f32], position: f32) -> f32 {
+    let index = position.floor() as usize;
+    if index + 1 >= data.len() {
+        return data.get(index).copied().unwrap_or(0.0);
+    }
+    let fraction = position - index as f32;
+    data[index] + (data[index + 1] - data[index]) * fraction
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Interpolation {
+    Nearest,
+    #[default]
+    Linear,
+    /// Catmull-Rom Hermite spline through the four surrounding samples.
+    Cubic,
+}
+
+impl Interpolation {
+    pub fn sample(&self, data: &[f32], position: f32) -> f32 {
+        match self {
+            Interpolation::Nearest => data.get(position.round() as usize).copied().unwrap_or(0.0),
+            Interpolation::Linear => sample_at(data, position),
+            Interpolation::Cubic => {
+                let index = position.floor() as usize;
+                if index >= data.len() {
+                    return 0.0;
+                }
+                let at = |offset: isize| {
+                    let clamped = (index as isize + offset).clamp(0, data.len() as isize - 1);
+                    data[clamped as usize]
+                };
+                let (p0, p1, p2, p3) = (at(-1), at(0), at(1), at(2));
+                let x = position - index as f32;
+                let a = -0.5 * p0 + 1.5 * p1 - 1.5 * p2 + 0.5 * p3;
+                let b = p0 - 2.5 * p1 + 2.0 * p2 - 0.5 * p3;
+                let c = -0.5 * p0 + 0.5 * p2;
+                ((a * x + b) * x + c) * x + p1
+            }
+        }
+    }
+}
+
+fn poly_blep(phase: f32, dt: f32) -> f32 {
+    if phase < dt {
+        let x = phase / dt;
+        x + x - x * x - 1.0
+    } else if phase > 1.0 - dt {
+        let x = (phase - 1.0) / dt;
+        x * x + x + x + 1.0
+    } else {
+        0.0
+    }
+}
+
+pub fn square_blep(phase: f32, dt: f32) -> f32 {
+    square(phase) + poly_blep(phase, dt) - poly_blep((phase + 0.5).fract(), dt)
+}
+
+pub fn sawtooth_blep(phase: f32, dt: f32) -> f32 {
+    sawtooth(phase) - poly_blep((phase + 0.5).fract(), dt)
+}
+
+struct PinkFilter {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+}
+
+impl PinkFilter {
+    fn new() -> PinkFilter {
+        PinkFilter {
+            b0: 0.0,
+            b1: 0.0,
+            b2: 0.0,
+        }
+    }
+    fn next(&mut self, white: f32) -> f32 {
+        self.b0 = 0.99765 * self.b0 + white * 0.099_046;
+        self.b1 = 0.963 * self.b1 + white * 0.296_516_4;
+        self.b2 = 0.57 * self.b2 + white * 1.052_691_3;
+        (self.b0 + self.b1 + self.b2 + white * 0.1848) * 0.11
+    }
+}
+
+pub struct OscillatorState {
+    pub phase: f32,
+    pub modulator_phase: f32,
+    pub position: f32,
+    /// How far through its note the voice is, from 0 to 1; `Morph` blends by it.
+    pub progress: f32,
+    /// Frequency at which a `Sample` plays back at its recorded speed.
+    pub reference: f32,
+    pub loop_sample: bool,
+    rng: Rng,
+    pink: PinkFilter,
+    string: Vec<f32>,
+    string_index: usize,
+    morph: Option<Box<[OscillatorState; 2]>>,
+}
+
+impl OscillatorState {
+    pub fn new(seed: u64) -> OscillatorState {
+        OscillatorState {
+            phase: 0.0,
+            modulator_phase: 0.0,
+            position: 0.0,
+            progress: 0.0,
+            reference: 440.0,
+            loop_sample: false,
+            rng: Rng::new(seed),
+            pink: PinkFilter::new(),
+            string: Vec::new(),
+            string_index: 0,
+            morph: None,
+        }
+    }
+    pub fn advance(&mut self, phase_increment: f32) {
+        self.phase += phase_increment;
+        self.phase -= self.phase.floor();
+    }
+}
+
+/// ```
+/// use music::oscillator::{apply_wave_function, OscillatorState};
+/// use music::WaveFunction;
+///
+/// let mut state = OscillatorState::new(0);
+/// state.advance(0.25);
+/// let level = apply_wave_function(&WaveFunction::Square, 0.0, 440.0, 48000.0, &mut state);
+/// assert_eq!(level, 1.0);
+/// ```
+pub fn apply_wave_function(
+    function: &WaveFunction,
+    t: f32,
+    f: f32,
+    sample_rate: f32,
+    state: &mut OscillatorState,
+) -> f32 {
+    let phase = state.phase;
+    match function {
+        WaveFunction::Square => square(phase),
+        WaveFunction::Sawtooth => sawtooth(phase),
+        WaveFunction::Triangle => triangle(phase),
+        WaveFunction::Sine => sine(phase),
+        WaveFunction::SquareBandLimited => square_blep(phase, f / sample_rate),
+        WaveFunction::SawtoothBandLimited => sawtooth_blep(phase, f / sample_rate),
+        WaveFunction::WhiteNoise => state.rng.next_bipolar(),
+        WaveFunction::PinkNoise => {
+            let white = state.rng.next_bipolar();
+            state.pink.next(white)
+        }
+        WaveFunction::Custom(function) => function(t, f),
+        WaveFunction::Rest => 0.0,
+        WaveFunction::Sample {
+            data,
+            sample_rate: source_rate,
+            interpolation,
+        } => {
+            let level = interpolation.sample(data, state.position);
+            state.position += f / state.reference * *source_rate as f32 / sample_rate;
+            if state.loop_sample && !data.is_empty() {
+                state.position %= data.len() as f32;
+            }
+            level
+        }
+        WaveFunction::Pulse { duty } => pulse(phase, *duty),
+        WaveFunction::Fm { ratio, index } => {
+            let modulator = sine(state.modulator_phase);
+            state.modulator_phase += ratio * f / sample_rate;
+            state.modulator_phase -= state.modulator_phase.floor();
+            sine(phase + index * modulator / (2.0 * std::f32::consts::PI))
+        }
+        WaveFunction::Additive { harmonics } => additive(phase, harmonics),
+        WaveFunction::Pluck => pluck(state, f, sample_rate),
+        WaveFunction::Morph { from, to } => {
+            // Each side keeps its own state so stateful functions advance once per sample.
+            let mut sides = match state.morph.take() {
+                Some(sides) => sides,
+                None => Box::new([
+                    OscillatorState::new(state.rng.next_u64()),
+                    OscillatorState::new(state.rng.next_u64()),
+                ]),
+            };
+            for side in sides.iter_mut() {
+                side.phase = state.phase;
+                side.progress = state.progress;
+                side.reference = state.reference;
+                side.loop_sample = state.loop_sample;
+            }
+            let [from_state, to_state] = &mut *sides;
+            let from = apply_wave_function(from, t, f, sample_rate, from_state);
+            let to = apply_wave_function(to, t, f, sample_rate, to_state);
+            state.morph = Some(sides);
+            from + (to - from) * state.progress
+        }
+    }
+}
+
+const PLUCK_DECAY: f32 = 0.996;
+
+/// Karplus-Strong: the string is a noise-filled delay line one period long, averaged
+/// with its neighbour on every pass so high harmonics die away first.
+fn pluck(state: &mut OscillatorState, f: f32, sample_rate: f32) -> f32 {
+    if state.string.is_empty() {
+        let length = ((sample_rate / f).round().min(sample_rate) as usize).max(2);
+        state.string = (0..length).map(|_| state.rng.next_bipolar()).collect();
+    }
+    let length = state.string.len();
+    let index = state.string_index;
+    let next = (index + 1) % length;
+    let level = state.string[index];
+    state.string[index] = PLUCK_DECAY * 0.5 * (level + state.string[next]);
+    state.string_index = next;
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(
+        function: &WaveFunction,
+        frequency: f32,
+        sample_rate: f32,
+        length: usize,
+    ) -> Vec<f32> {
+        let mut state = OscillatorState::new(1);
+        (0..length)
+            .map(|index| {
+                let t = index as f32 / sample_rate;
+                let level = apply_wave_function(function, t, frequency, sample_rate, &mut state);
+                state.advance(frequency / sample_rate);
+                level
+            })
+            .collect()
+    }
+
+    /// Energy folded back from above Nyquist lands between the true harmonics.
+    fn aliased_energy(wave: &[f32], fundamental: f32, sample_rate: f32) -> f32 {
+        crate::effects::spectrum(wave, 0, wave.len(), sample_rate)
+            .into_iter()
+            .filter(|(frequency, _)| {
+                let harmonic = (frequency / fundamental).round() * fundamental;
+                (frequency - harmonic).abs() > 100.0
+            })
+            .map(|(_, magnitude)| magnitude * magnitude)
+            .sum()
+    }
+
+    #[test]
+    fn band_limited_sawtooth_aliases_less() {
+        for (naive, band_limited) in [
+            (WaveFunction::Sawtooth, WaveFunction::SawtoothBandLimited),
+            (WaveFunction::Square, WaveFunction::SquareBandLimited),
+        ] {
+            let naive = aliased_energy(&render(&naive, 5000.0, 48000.0, 8192), 5000.0, 48000.0);
+            let band_limited = aliased_energy(
+                &render(&band_limited, 5000.0, 48000.0, 8192),
+                5000.0,
+                48000.0,
+            );
+            assert!(band_limited < naive * 0.1, "{} vs {}", band_limited, naive);
+        }
+    }
+
+    /// Mean power per spectrum bin in `low..high` Hz, averaged over consecutive windows.
+    fn band_power(wave: &[f32], low: f32, high: f32, sample_rate: f32) -> f32 {
+        let mut total = 0.0;
+        let mut bins = 0;
+        for start in (0..wave.len() - 4096).step_by(4096) {
+            for (frequency, magnitude) in crate::effects::spectrum(wave, start, 4096, sample_rate) {
+                if frequency >= low && frequency < high {
+                    total += magnitude * magnitude;
+                    bins += 1;
+                }
+            }
+        }
+        total / bins as f32
+    }
+
+    #[test]
+    fn white_noise_is_flat_and_pink_noise_rolls_off() {
+        let white = render(&WaveFunction::WhiteNoise, 440.0, 48000.0, 48000 * 4);
+        let tilt = band_power(&white, 200.0, 1000.0, 48000.0)
+            / band_power(&white, 8000.0, 16000.0, 48000.0);
+        assert!((0.7..1.4).contains(&tilt), "white tilt {}", tilt);
+        let pink = render(&WaveFunction::PinkNoise, 440.0, 48000.0, 48000 * 4);
+        let tilt =
+            band_power(&pink, 200.0, 1000.0, 48000.0) / band_power(&pink, 8000.0, 16000.0, 48000.0);
+        assert!(tilt > 10.0, "pink tilt {}", tilt);
+        assert_eq!(
+            pink,
+            render(&WaveFunction::PinkNoise, 440.0, 48000.0, 48000 * 4)
+        );
+    }
+
+    #[test]
+    fn pulse_duty_changes_timbre() {
+        let narrow = render(&WaveFunction::Pulse { duty: 0.1 }, 100.0, 8000.0, 8000);
+        let half = render(&WaveFunction::Pulse { duty: 0.5 }, 100.0, 8000.0, 8000);
+        assert_eq!(half, render(&WaveFunction::Square, 100.0, 8000.0, 8000));
+        let mean = |wave: &[f32]| wave.iter().sum::<f32>() / wave.len() as f32;
+        assert!((mean(&narrow) + 0.8).abs() < 0.01);
+        assert!(mean(&half).abs() < 0.01);
+        assert_eq!(pulse(0.001, 0.0), 1.0);
+        assert_eq!(pulse(0.999, 1.0), -1.0);
+    }
+
+    #[test]
+    fn fm_index_widens_sidebands() {
+        let significant = |index: f32| {
+            let wave = render(&WaveFunction::Fm { ratio: 1.0, index }, 250.0, 8000.0, 8192);
+            crate::effects::spectrum(&wave, 0, 8192, 8000.0)
+                .into_iter()
+                .filter(|(frequency, magnitude)| frequency % 250.0 < 0.1 && *magnitude > 0.02)
+                .count()
+        };
+        let (none, low, high) = (significant(0.0), significant(0.5), significant(4.0));
+        assert_eq!(none, 1);
+        assert!(low > none && high > low, "{} {} {}", none, low, high);
+    }
+
+    #[test]
+    fn single_harmonic_is_pure_sine() {
+        let additive = render(
+            &WaveFunction::Additive {
+                harmonics: vec![1.0],
+            },
+            440.0,
+            48000.0,
+            4800,
+        );
+        assert_eq!(additive, render(&WaveFunction::Sine, 440.0, 48000.0, 4800));
+        let rich = WaveFunction::Additive {
+            harmonics: vec![1.0, -0.5, 0.75, 0.25],
+        };
+        assert!(render(&rich, 440.0, 48000.0, 4800)
+            .iter()
+            .all(|sample| sample.abs() <= 1.0));
+        assert_eq!(super::additive(0.3, &[]), 0.0);
+    }
+
+    #[test]
+    fn sweep_ends_near_target_frequency() {
+        let frequency = |wave: &[f32]| {
+            let crossings: Vec<f32> = wave
+                .windows(2)
+                .enumerate()
+                .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+                .map(|(index, pair)| index as f32 + pair[0] / (pair[0] - pair[1]))
+                .collect();
+            let periods = (crossings.len() - 1) as f32;
+            periods * 48000.0 / (crossings[crossings.len() - 1] - crossings[0])
+        };
+        for logarithmic in [false, true] {
+            let wave = sweep(100.0, 2000.0, 2.0, 48000, logarithmic);
+            assert_eq!(wave.len(), 96000);
+            let end = frequency(&wave[96000 - 480..]);
+            assert!((end - 2000.0).abs() < 30.0, "end {}", end);
+            let start = frequency(&wave[..4800]);
+            assert!(start > 100.0 && start < 160.0, "start {}", start);
+            let largest_step = wave
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).abs())
+                .fold(0.0, f32::max);
+            assert!(largest_step < 2.0 * std::f32::consts::PI * 2000.0 / 48000.0 + 1e-3);
+        }
+    }
+
+    #[test]
+    fn oscillators_are_periodic_and_bounded() {
+        let oscillators: [fn(f32) -> f32; 4] = [square, sawtooth, triangle, sine];
+        for (index, oscillator) in oscillators.iter().enumerate() {
+            for step in 0..100 {
+                let phase = step as f32 / 100.0;
+                let level = oscillator(phase);
+                assert!((-1.0..=1.0).contains(&level), "{} at {}", index, phase);
+                assert!((oscillator(phase + 1.0) - level).abs() < 1e-4, "{}", index);
+            }
+        }
+        assert_eq!(pulse(0.3, 0.5), 1.0);
+        assert_eq!(pulse(0.7, 0.5), -1.0);
+        let mut state = OscillatorState::new(1);
+        let level = apply_wave_function(&WaveFunction::Square, 0.0, 440.0, 8000.0, &mut state);
+        assert_eq!(level.abs(), 1.0);
+    }
+
+    #[test]
+    fn cubic_interpolation_beats_nearest() {
+        let curve: Vec<f32> = (0..16).map(|index| sine(index as f32 / 16.0)).collect();
+        let position = 5.4;
+        let exact = sine(position / 16.0);
+        let error =
+            |interpolation: Interpolation| (interpolation.sample(&curve, position) - exact).abs();
+        assert!(error(Interpolation::Cubic) < error(Interpolation::Linear));
+        assert!(error(Interpolation::Linear) < error(Interpolation::Nearest));
+        assert!(error(Interpolation::Cubic) < 0.01);
+    }
+
+    #[test]
+    fn morph_midpoint_averages_both_waveforms() {
+        let morph = WaveFunction::Morph {
+            from: Box::new(WaveFunction::Sine),
+            to: Box::new(WaveFunction::Square),
+        };
+        let mut state = OscillatorState::new(1);
+        for (progress, step) in [(0.0, 3), (0.5, 7), (0.5, 30), (1.0, 12)] {
+            state.phase = step as f32 / 40.0;
+            state.progress = progress;
+            let level = apply_wave_function(&morph, 0.0, 440.0, 8000.0, &mut state);
+            let (from, to) = (sine(state.phase), square(state.phase));
+            let expected = from + (to - from) * progress;
+            assert!((level - expected).abs() < 1e-6, "{} at {}", level, progress);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+
+pub fn note_from_name(name: &str) -> Option<f32> {
+    let mut chars = name.chars();
+    let semitone = match chars.next()? {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = if let Some(octave) = rest.strip_prefix('#') {
+        (1, octave)
+    } else if let Some(octave) = rest.strip_prefix('b') {
+        (-1, octave)
+    } else {
+        (0, rest)
+    };
+    let octave: i32 = octave.parse().ok()?;
+    Some(((octave - 4) * 12 + semitone + accidental - 9) as f32)
+}
+
+pub fn chord_from_name(name: &str, octave: i32) -> Option<Vec<f32>> {
+    let root_length = match name.get(1..2) {
+        Some("#") | Some("b") => 2,
+        _ => 1,
+    };
+    let root = note_from_name(&format!("{}{}", name.get(..root_length)?, octave))?;
+    let intervals: &[f32] = match &name[root_length..] {
+        "" | "maj" | "M" => &[0.0, 4.0, 7.0],
+        "m" | "min" => &[0.0, 3.0, 7.0],
+        "7" | "dom7" => &[0.0, 4.0, 7.0, 10.0],
+        "maj7" | "M7" => &[0.0, 4.0, 7.0, 11.0],
+        "m7" | "min7" => &[0.0, 3.0, 7.0, 10.0],
+        "dim" => &[0.0, 3.0, 6.0],
+        "aug" => &[0.0, 4.0, 8.0],
+        "sus2" => &[0.0, 2.0, 7.0],
+        "sus4" => &[0.0, 5.0, 7.0],
+        _ => return None,
+    };
+    Some(intervals.iter().map(|interval| root + interval).collect())
+}
+
+pub fn get_frequency_from_note(note: f32, reference: f32) -> f32 {
+    reference * 2.0f32.powf(note / 12.0)
+}
+
+/// Returns NaN for non-positive frequencies, which have no pitch.
+pub fn note_from_frequency(freq: f32, reference: f32) -> f32 {
+    if freq <= 0.0 || reference <= 0.0 {
+        return f32::NAN;
+    }
+    12.0 * (freq / reference).log2()
+}
+
+const JUST_RATIOS: [f32; 12] = [
+    1.0,
+    16.0 / 15.0,
+    9.0 / 8.0,
+    6.0 / 5.0,
+    5.0 / 4.0,
+    4.0 / 3.0,
+    45.0 / 32.0,
+    3.0 / 2.0,
+    8.0 / 5.0,
+    5.0 / 3.0,
+    9.0 / 5.0,
+    15.0 / 8.0,
+];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Tuning {
+    EqualTemperament { divisions: u32 },
+    JustIntonation,
+    Scala { cents: Vec<f32> },
+}
+
+impl Tuning {
+    pub fn frequency(&self, note: f32, reference: f32) -> f32 {
+        match self {
+            Tuning::EqualTemperament { divisions } => {
+                reference * 2.0f32.powf(note / *divisions as f32)
+            }
+            Tuning::JustIntonation => {
+                let octave = (note / 12.0).floor();
+                let degree = note - octave * 12.0;
+                let index = (degree.floor() as usize).min(11);
+                let low = JUST_RATIOS[index];
+                let high = if index == 11 {
+                    2.0
+                } else {
+                    JUST_RATIOS[index + 1]
+                };
+                let ratio = low * (high / low).powf(degree - index as f32);
+                reference * 2.0f32.powf(octave) * ratio
+            }
+            Tuning::Scala { cents } if cents.is_empty() => reference * 2.0f32.powf(note / 12.0),
+            Tuning::Scala { cents } => {
+                let degrees = cents.len() as f32;
+                let period = cents[cents.len() - 1];
+                let octave = (note / degrees).floor();
+                let degree = note - octave * degrees;
+                let index = (degree.floor() as usize).min(cents.len() - 1);
+                let low = if index == 0 { 0.0 } else { cents[index - 1] };
+                let offset = low + (cents[index] - low) * (degree - index as f32);
+                reference * 2.0f32.powf((octave * period + offset) / 1200.0)
+            }
+        }
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Tuning {
+        Tuning::EqualTemperament { divisions: 12 }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Pitch {
+    Note(f32),
+    Hz(f32),
+}
+
+impl Pitch {
+    pub fn transposed(&self, semitones: f32) -> Pitch {
+        match self {
+            Pitch::Note(note) => Pitch::Note(note + semitones),
+            Pitch::Hz(frequency) => Pitch::Hz(frequency * 2.0f32.powf(semitones / 12.0)),
+        }
+    }
+}
+
+const MAJOR_INTERVALS: [f32; 7] = [0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 11.0];
+const MINOR_INTERVALS: [f32; 7] = [0.0, 2.0, 3.0, 5.0, 7.0, 8.0, 10.0];
+
+#[derive(Clone, Copy)]
+pub enum Scale {
+    Major,
+    Minor,
+}
+
+impl Scale {
+    pub fn intervals(&self) -> [f32; 7] {
+        match self {
+            Scale::Major => MAJOR_INTERVALS,
+            Scale::Minor => MINOR_INTERVALS,
+        }
+    }
+}
+
+fn scale_from(root_note: f32, intervals: [f32; 7]) -> [f32; 7] {
+    let mut notes = intervals;
+    for note in notes.iter_mut() {
+        *note += root_note;
+    }
+    notes
+}
+
+pub fn major_scale(root_note: f32) -> [f32; 7] {
+    scale_from(root_note, MAJOR_INTERVALS)
+}
+
+pub fn minor_scale(root_note: f32) -> [f32; 7] {
+    scale_from(root_note, MINOR_INTERVALS)
+}
+
+/// Degrees count from 0 (the root) and wrap into higher octaves past 6.
+pub fn scale_degree(root: f32, degree: u32, scale: Scale) -> f32 {
+    let octave = (degree / 7) as f32;
+    root + octave * 12.0 + scale.intervals()[(degree % 7) as usize]
+}
+
+/// Ties between two scale notes go to the lower one.
+pub fn snap_to_scale(note: f32, root: f32, scale: Scale) -> f32 {
+    let relative = note - root;
+    let octave = (relative / 12.0).floor();
+    let degree = relative - octave * 12.0;
+    let nearest = scale
+        .intervals()
+        .iter()
+        .copied()
+        .chain(std::iter::once(12.0))
+        .fold(0.0f32, |nearest, interval| {
+            if (interval - degree).abs() < (nearest - degree).abs() {
+                interval
+            } else {
+                nearest
+            }
+        });
+    root + octave * 12.0 + nearest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scientific_pitch_names() {
+        assert_eq!(note_from_name("A4"), Some(0.0));
+        assert_eq!(note_from_name("A5"), Some(12.0));
+        assert_eq!(note_from_name("C4"), Some(-9.0));
+        assert_eq!(note_from_name("A#3"), Some(-11.0));
+        assert_eq!(note_from_name("Bb5"), Some(13.0));
+        assert_eq!(note_from_name("H4"), None);
+        assert_eq!(note_from_name("C"), None);
+        assert_eq!(note_from_name(""), None);
+    }
+
+    #[test]
+    fn tunings_map_notes_to_frequencies() {
+        let twelve = Tuning::default();
+        for note in [-21.0, -9.0, 0.0, 3.5, 12.0, 19.0] {
+            let expected = get_frequency_from_note(note, 440.0);
+            assert!((twelve.frequency(note, 440.0) - expected).abs() < 1e-3);
+        }
+        let quarter_tones = Tuning::EqualTemperament { divisions: 24 };
+        let quarter_tone = quarter_tones.frequency(1.0, 440.0);
+        assert!((quarter_tone - 440.0 * 2.0f32.powf(1.0 / 24.0)).abs() < 1e-3);
+        assert!((quarter_tones.frequency(24.0, 440.0) - 880.0).abs() < 1e-3);
+        let just = Tuning::JustIntonation;
+        assert!((just.frequency(7.0, 440.0) - 660.0).abs() < 1e-3);
+        assert!((just.frequency(-12.0, 440.0) - 220.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn scales_follow_their_intervals() {
+        assert_eq!(major_scale(0.0), [0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 11.0]);
+        assert_eq!(major_scale(-9.0)[4], -2.0);
+        assert_eq!(minor_scale(0.0), [0.0, 2.0, 3.0, 5.0, 7.0, 8.0, 10.0]);
+        assert_eq!(scale_degree(3.0, 0, Scale::Major), 3.0);
+        assert_eq!(scale_degree(0.0, 4, Scale::Minor), 7.0);
+        assert_eq!(scale_degree(0.0, 9, Scale::Major), 16.0);
+    }
+
+    #[test]
+    fn frequency_converts_back_to_note() {
+        for note in [-30.0, -9.5, 0.0, 5.0, 13.25] {
+            let frequency = get_frequency_from_note(note, 440.0);
+            assert!((note_from_frequency(frequency, 440.0) - note).abs() < 1e-4);
+        }
+        assert!(note_from_frequency(0.0, 440.0).is_nan());
+        assert!(note_from_frequency(-10.0, 440.0).is_nan());
+    }
+
+    #[test]
+    fn parses_chord_symbols() {
+        assert_eq!(chord_from_name("Cmaj", 4), Some(vec![-9.0, -5.0, -2.0]));
+        assert_eq!(chord_from_name("Am7", 4), Some(vec![0.0, 3.0, 7.0, 10.0]));
+        assert_eq!(
+            chord_from_name("G7", 3),
+            Some(vec![-14.0, -10.0, -7.0, -4.0])
+        );
+        assert_eq!(
+            chord_from_name("Dmin7", 4),
+            Some(vec![-7.0, -4.0, 0.0, 3.0])
+        );
+        assert_eq!(
+            chord_from_name("F#maj7", 4),
+            Some(vec![-3.0, 1.0, 4.0, 8.0])
+        );
+        assert_eq!(chord_from_name("Cwhat", 4), None);
+        assert_eq!(chord_from_name("", 4), None);
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+use std::sync::mpsc;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::error::RenderError;
+
+#[derive(Debug)]
+pub enum PlaybackError {
+    NoOutputDevice,
+    DefaultConfig(cpal::DefaultStreamConfigError),
+    BuildStream(cpal::BuildStreamError),
+    PlayStream(cpal::PlayStreamError),
+    Stream(cpal::StreamError),
+    Render(RenderError),
+}
+
+impl fmt::Display for PlaybackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlaybackError::NoOutputDevice => write!(f, "no audio output device available"),
+            PlaybackError::DefaultConfig(error) => write!(f, "{}", error),
+            PlaybackError::BuildStream(error) => write!(f, "{}", error),
+            PlaybackError::PlayStream(error) => write!(f, "{}", error),
+            PlaybackError::Stream(error) => write!(f, "{}", error),
+            PlaybackError::Render(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for PlaybackError {}
+
+impl From<cpal::DefaultStreamConfigError> for PlaybackError {
+    fn from(error: cpal::DefaultStreamConfigError) -> PlaybackError {
+        PlaybackError::DefaultConfig(error)
+    }
+}
+
+impl From<cpal::BuildStreamError> for PlaybackError {
+    fn from(error: cpal::BuildStreamError) -> PlaybackError {
+        PlaybackError::BuildStream(error)
+    }
+}
+
+impl From<cpal::PlayStreamError> for PlaybackError {
+    fn from(error: cpal::PlayStreamError) -> PlaybackError {
+        PlaybackError::PlayStream(error)
+    }
+}
+
+impl From<cpal::StreamError> for PlaybackError {
+    fn from(error: cpal::StreamError) -> PlaybackError {
+        PlaybackError::Stream(error)
+    }
+}
+
+impl From<RenderError> for PlaybackError {
+    fn from(error: RenderError) -> PlaybackError {
+        PlaybackError::Render(error)
+    }
+}
+
+pub fn play_buffer(wave: Vec<f32>, sample_rate: u32) -> Result<(), PlaybackError> {
+    let host = cpal::default_host();
+    let device = host
+        .default_output_device()
+        .ok_or(PlaybackError::NoOutputDevice)?;
+    let channels = device.default_output_config()?.channels();
+    let config = cpal::StreamConfig {
+        channels,
+        sample_rate: cpal::SampleRate(sample_rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+    let (finished_sender, finished_receiver) = mpsc::channel();
+    let error_sender = finished_sender.clone();
+    let mut position = 0;
+    let stream = device.build_output_stream(
+        &config,
+        move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+            for frame in data.chunks_mut(channels as usize) {
+                let sample = wave.get(position).copied().unwrap_or(0.0);
+                for output in frame.iter_mut() {
+                    *output = sample;
+                }
+                position += 1;
+            }
+            if position >= wave.len() {
+                let _ = finished_sender.send(Ok(()));
+            }
+        },
+        move |error| {
+            let _ = error_sender.send(Err(error));
+        },
+        None,
+    )?;
+    stream.play()?;
+    match finished_receiver.recv() {
+        Ok(Err(error)) => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Generator, WaveFunction};
+
+    #[test]
+    fn play_renders_a_buffer() {
+        let mut generator = Generator::new();
+        generator.add_music_element(WaveFunction::Sine, 0.0, 0.1, 0.0, 1.0);
+        let wave = generator.render_to_buffer(8000, 0.0).unwrap();
+        assert_eq!(wave.len(), 800);
+        assert!(wave.iter().any(|sample| *sample != 0.0));
+        assert!(generator.play(1, 0.0).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+#[cfg(feature = "fs")]
+use std::io;
+
+use crate::pitch::Tuning;
+
+#[derive(Debug)]
+pub enum ScalaError {
+    #[cfg(feature = "fs")]
+    Io(io::Error),
+    MissingCount,
+    InvalidCount(String),
+    InvalidPitch(String),
+    TooFewPitches {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ScalaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "fs")]
+            ScalaError::Io(error) => write!(f, "{}", error),
+            ScalaError::MissingCount => write!(f, "missing note count"),
+            ScalaError::InvalidCount(line) => write!(f, "invalid note count {:?}", line),
+            ScalaError::InvalidPitch(line) => write!(f, "invalid pitch {:?}", line),
+            ScalaError::TooFewPitches { expected, found } => {
+                write!(f, "expected {} pitches, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScalaError {}
+
+#[cfg(feature = "fs")]
+impl From<io::Error> for ScalaError {
+    fn from(error: io::Error) -> ScalaError {
+        ScalaError::Io(error)
+    }
+}
+
+#[cfg(feature = "fs")]
+pub fn load_scala(path: &str) -> Result<Tuning, ScalaError> {
+    parse_scala(&std::fs::read_to_string(path)?)
+}
+
+pub fn parse_scala(source: &str) -> Result<Tuning, ScalaError> {
+    let mut lines = source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('!'));
+    lines.next().ok_or(ScalaError::MissingCount)?;
+    let count_line = lines.next().ok_or(ScalaError::MissingCount)?;
+    let count: usize = first_word(count_line)
+        .parse()
+        .map_err(|_| ScalaError::InvalidCount(count_line.to_string()))?;
+    if count == 0 {
+        return Err(ScalaError::InvalidCount(count_line.to_string()));
+    }
+    let cents = lines
+        .take(count)
+        .map(parse_pitch)
+        .collect::<Result<Vec<f32>, ScalaError>>()?;
+    if cents.len() < count {
+        return Err(ScalaError::TooFewPitches {
+            expected: count,
+            found: cents.len(),
+        });
+    }
+    Ok(Tuning::Scala { cents })
+}
+
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+fn parse_pitch(line: &str) -> Result<f32, ScalaError> {
+    let invalid = || ScalaError::InvalidPitch(line.to_string());
+    let value = first_word(line);
+    if value.contains('.') {
+        return value.parse().map_err(|_| invalid());
+    }
+    let ratio = match value.split_once('/') {
+        Some((numerator, denominator)) => {
+            let numerator: f32 = numerator.parse().map_err(|_| invalid())?;
+            let denominator: f32 = denominator.parse().map_err(|_| invalid())?;
+            numerator / denominator
+        }
+        None => value.parse().map_err(|_| invalid())?,
+    };
+    if ratio <= 0.0 || !ratio.is_finite() {
+        return Err(invalid());
+    }
+    Ok(1200.0 * ratio.log2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_inline_scala_file() {
+        let source = "! example.scl\n!\nJust fifth and octave\n 3\n!\n 100.0 cents\n 3/2\n 2\n";
+        let cents = match parse_scala(source).unwrap() {
+            Tuning::Scala { cents } => cents,
+            _ => panic!("expected a Scala tuning"),
+        };
+        assert_eq!(cents.len(), 3);
+        assert_eq!(cents[0], 100.0);
+        assert!((cents[1] - 701.955).abs() < 0.01);
+        assert!((cents[2] - 1200.0).abs() < 0.01);
+        assert!(matches!(
+            parse_scala("short\n 3\n 3/2\n"),
+            Err(ScalaError::TooFewPitches {
+                expected: 3,
+                found: 1
+            })
+        ));
+    }
+}
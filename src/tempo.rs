@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::interpolate_points;
+use crate::error::RenderError;
+use crate::{Envelope, Generator, WaveFunction};
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Tempo {
+    pub bpm: f32,
+}
+
+impl Tempo {
+    pub fn new(bpm: f32) -> Tempo {
+        Tempo { bpm }
+    }
+    pub fn beats_to_seconds(&self, beats: f32) -> f32 {
+        beats * 60.0 / self.bpm
+    }
+}
+
+impl Default for Tempo {
+    fn default() -> Tempo {
+        Tempo::new(120.0)
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct TimeSignature {
+    pub beats_per_bar: u32,
+    pub beat_unit: u32,
+}
+
+impl TimeSignature {
+    pub fn new(beats_per_bar: u32, beat_unit: u32) -> TimeSignature {
+        TimeSignature {
+            beats_per_bar,
+            beat_unit,
+        }
+    }
+    /// Converts beats of `beat_unit` into the quarter-note beats a `Tempo` counts.
+    pub fn quarter_beats(&self, beats: f32) -> f32 {
+        beats * 4.0 / self.beat_unit.max(1) as f32
+    }
+    /// Bars and beats count from 1, as in sheet music.
+    pub fn bar_to_beats(&self, bar: u32, beat: f32) -> f32 {
+        bar.saturating_sub(1) as f32 * self.beats_per_bar as f32 + beat - 1.0
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> TimeSignature {
+        TimeSignature::new(4, 4)
+    }
+}
+
+/// Seconds elapsed by `beat` under a tempo that moves linearly between `(beat, bpm)`
+/// points, holding the first and last tempo outside them.
+pub fn tempo_curve_seconds(points: &[(f32, f32)], beat: f32) -> f32 {
+    if points.is_empty() {
+        return Tempo::default().beats_to_seconds(beat);
+    }
+    let mut boundaries = vec![0.0];
+    boundaries.extend(
+        points
+            .iter()
+            .map(|&(point_beat, _)| point_beat)
+            .filter(|&point_beat| point_beat > 0.0 && point_beat < beat),
+    );
+    boundaries.push(beat.max(0.0));
+    let bpm_at = |beat: f32| interpolate_points(points, beat).max(1.0);
+    boundaries
+        .windows(2)
+        .map(|segment| {
+            let (start, end) = (segment[0], segment[1]);
+            let (start_bpm, end_bpm) = (bpm_at(start), bpm_at(end));
+            if (end_bpm - start_bpm).abs() < 1e-3 {
+                60.0 * (end - start) / start_bpm
+            } else {
+                60.0 * (end - start) / (end_bpm - start_bpm) * (end_bpm / start_bpm).ln()
+            }
+        })
+        .sum()
+}
+
+/// Lengths are counted in quarter-note beats, the unit the beat-based helpers take.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum NoteValue {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    Dotted(Box<NoteValue>),
+    Triplet(Box<NoteValue>),
+}
+
+impl NoteValue {
+    pub fn to_beats(&self) -> f32 {
+        match self {
+            NoteValue::Whole => 4.0,
+            NoteValue::Half => 2.0,
+            NoteValue::Quarter => 1.0,
+            NoteValue::Eighth => 0.5,
+            NoteValue::Sixteenth => 0.25,
+            NoteValue::Dotted(value) => value.to_beats() * 1.5,
+            NoteValue::Triplet(value) => value.to_beats() * 2.0 / 3.0,
+        }
+    }
+}
+
+pub fn metronome(
+    bpm: f32,
+    beats: u32,
+    sample_rate: u32,
+    beats_per_bar: Option<u32>,
+) -> Result<Vec<f32>, RenderError> {
+    let tempo = Tempo::new(bpm);
+    let mut generator = Generator::new();
+    generator.add_rest(0.0, tempo.beats_to_seconds(beats as f32));
+    for beat in 0..beats {
+        let accented = match beats_per_bar {
+            Some(beats_per_bar) => beats_per_bar > 0 && beat % beats_per_bar == 0,
+            None => false,
+        };
+        let (note, amplitude) = if accented { (36.0, 1.0) } else { (24.0, 0.6) };
+        let time = tempo.beats_to_seconds(beat as f32);
+        let click = generator.add_music_element(WaveFunction::Sine, time, 0.03, note, amplitude);
+        click.envelope = Some(Envelope::new(0.001, 0.029, 0.0, 0.0));
+    }
+    generator.render_to_buffer(sample_rate, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_beats_to_seconds() {
+        assert_eq!(Tempo::new(120.0).beats_to_seconds(1.0), 0.5);
+        assert_eq!(Tempo::new(60.0).beats_to_seconds(3.0), 3.0);
+        assert_eq!(Tempo::new(90.0).beats_to_seconds(1.5), 1.0);
+        let mut generator = Generator::new();
+        generator.tempo = Some(Tempo::new(120.0));
+        let element = generator.add_note_at_beat(WaveFunction::Sine, 2.0, 1.0, 0.0, 1.0);
+        assert_eq!((element.time, element.duration), (1.0, 0.5));
+    }
+
+    #[test]
+    fn metronome_clicks_on_each_beat() {
+        let wave = metronome(120.0, 4, 8000, Some(2)).unwrap();
+        assert_eq!(wave.len(), 16000);
+        let onsets: Vec<usize> = (0..wave.len())
+            .filter(|&index| wave[index] != 0.0 && (index == 0 || wave[index - 1] == 0.0))
+            .collect();
+        let starts: Vec<usize> = onsets
+            .iter()
+            .copied()
+            .filter(|&index| {
+                onsets
+                    .iter()
+                    .all(|&other| other >= index || index - other > 400)
+            })
+            .collect();
+        assert_eq!(starts.len(), 4);
+        for (beat, start) in starts.iter().enumerate() {
+            assert!(
+                start.abs_diff(beat * 4000) <= 2,
+                "beat {} at {}",
+                beat,
+                start
+            );
+        }
+        let peak =
+            |beat: usize| crate::effects::analyze(&wave[beat * 4000..beat * 4000 + 400]).peak;
+        assert!(peak(0) > peak(1) && peak(2) > peak(3));
+    }
+
+    #[test]
+    fn places_notes_by_bar_and_beat() {
+        let mut generator = Generator::new();
+        generator.tempo = Some(Tempo::new(120.0));
+        generator.time_signature = Some(TimeSignature::new(4, 4));
+        let element = generator.add_note_at_bar(WaveFunction::Sine, 2, 1.0, 2.0, 0.0, 1.0);
+        assert_eq!((element.time, element.duration), (2.0, 1.0));
+        generator.time_signature = Some(TimeSignature::new(6, 8));
+        let element = generator.add_note_at_bar(WaveFunction::Sine, 2, 1.0, 1.0, 0.0, 1.0);
+        assert_eq!((element.time, element.duration), (1.5, 0.25));
+    }
+
+    #[test]
+    fn dotted_and_triplet_values_convert_to_beats() {
+        let dotted_quarter = NoteValue::Dotted(Box::new(NoteValue::Quarter));
+        assert_eq!(dotted_quarter.to_beats(), 1.5);
+        let eighth_triplet = NoteValue::Triplet(Box::new(NoteValue::Eighth));
+        assert!((eighth_triplet.to_beats() - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(NoteValue::Whole.to_beats(), 4.0);
+        assert_eq!(NoteValue::Sixteenth.to_beats(), 0.25);
+    }
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "fs")]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use music::{Generator, WaveFunction};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("music-cli-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn renders_json_composition_to_wav() {
+    let mut generator = Generator::new();
+    generator.add_music_element(WaveFunction::Sine, 0.0, 0.5, 0.0, 1.0);
+    let input = temp_path("song.json");
+    let output = temp_path("song.wav");
+    std::fs::write(&input, generator.to_json().unwrap()).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_music"))
+        .args([&input, &output])
+        .arg("8000")
+        .arg("0.25")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let reader = hound::WavReader::open(&output).unwrap();
+    assert_eq!(reader.spec().sample_rate, 8000);
+    assert_eq!(reader.len(), 6000);
+    std::fs::remove_file(input).unwrap();
+    std::fs::remove_file(output).unwrap();
+}
+
+#[test]
+fn rejects_bad_input_with_error() {
+    let input = temp_path("broken.json");
+    let output = temp_path("broken.wav");
+    std::fs::write(&input, "{ not json").unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_music"))
+        .args([&input, &output])
+        .output()
+        .unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(
+        stderr.starts_with("error: invalid composition"),
+        "{}",
+        stderr
+    );
+    assert!(!output.exists());
+    std::fs::remove_file(input).unwrap();
+    let usage = Command::new(env!("CARGO_BIN_EXE_music")).output().unwrap();
+    assert_eq!(usage.status.code(), Some(2));
+}